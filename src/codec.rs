@@ -0,0 +1,135 @@
+use std::fmt;
+
+// Codecs by the names ffmpeg uses for them
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Codec {
+	H264,
+	Hevc,
+	Mpeg1Video,
+	Mpeg2Video,
+	Mpeg4,
+	Vc1,
+	Av1,
+	Vp8,
+	Vp9,
+	Ffv1,
+	Theora,
+	ProRes,
+
+	Aac,
+	Ac3,
+	Eac3,
+	Dts,
+	TrueHd,
+	Mlp,
+	Flac,
+	Opus,
+	Vorbis,
+	Alac,
+	Mp1,
+	Mp2,
+	Mp3,
+	PcmS16le,
+	PcmS24le,
+	PcmF32le,
+
+	Subrip,
+	Ass,
+	WebVtt,
+	MovText,
+	DvdSubtitle,
+	DvbSubtitle,
+	HdmvPgsSubtitle,
+	HdmvTextSubtitle,
+
+	Ttf,
+	Otf,
+
+	// Codecs nothing depends on keep their name
+	Unknown(String),
+}
+
+const NAMES: &[(Codec, &str)] = &[
+	(Codec::H264, "h264"),
+	(Codec::Hevc, "hevc"),
+	(Codec::Mpeg1Video, "mpeg1video"),
+	(Codec::Mpeg2Video, "mpeg2video"),
+	(Codec::Mpeg4, "mpeg4"),
+	(Codec::Vc1, "vc1"),
+	(Codec::Av1, "av1"),
+	(Codec::Vp8, "vp8"),
+	(Codec::Vp9, "vp9"),
+	(Codec::Ffv1, "ffv1"),
+	(Codec::Theora, "theora"),
+	(Codec::ProRes, "prores"),
+	(Codec::Aac, "aac"),
+	(Codec::Ac3, "ac3"),
+	(Codec::Eac3, "eac3"),
+	(Codec::Dts, "dts"),
+	(Codec::TrueHd, "truehd"),
+	(Codec::Mlp, "mlp"),
+	(Codec::Flac, "flac"),
+	(Codec::Opus, "opus"),
+	(Codec::Vorbis, "vorbis"),
+	(Codec::Alac, "alac"),
+	(Codec::Mp1, "mp1"),
+	(Codec::Mp2, "mp2"),
+	(Codec::Mp3, "mp3"),
+	(Codec::PcmS16le, "pcm_s16le"),
+	(Codec::PcmS24le, "pcm_s24le"),
+	(Codec::PcmF32le, "pcm_f32le"),
+	(Codec::Subrip, "subrip"),
+	(Codec::Ass, "ass"),
+	(Codec::WebVtt, "webvtt"),
+	(Codec::MovText, "mov_text"),
+	(Codec::DvdSubtitle, "dvd_subtitle"),
+	(Codec::DvbSubtitle, "dvb_subtitle"),
+	(Codec::HdmvPgsSubtitle, "hdmv_pgs_subtitle"),
+	(Codec::HdmvTextSubtitle, "hdmv_text_subtitle"),
+	(Codec::Ttf, "ttf"),
+	(Codec::Otf, "otf"),
+];
+
+// Encoders of the encode filter that write one of the codecs above
+const ENCODERS: &[(&str, Codec)] = &[
+	("libx264", Codec::H264),
+	("libx265", Codec::Hevc),
+	("libsvtav1", Codec::Av1),
+	("libaom-av1", Codec::Av1),
+	("librav1e", Codec::Av1),
+	("libvpx", Codec::Vp8),
+	("libvpx-vp9", Codec::Vp9),
+	("libtheora", Codec::Theora),
+	("prores_ks", Codec::ProRes),
+	("libfdk_aac", Codec::Aac),
+	("libopus", Codec::Opus),
+	("libvorbis", Codec::Vorbis),
+	("libmp3lame", Codec::Mp3),
+	("srt", Codec::Subrip),
+];
+
+impl Codec {
+	// Accepts the names of ffprobe and of ffmpeg encoders
+	pub fn parse(name: &str) -> Self {
+		let known = NAMES.iter().find(|(_, n)| *n == name).map(|(c, _)| c);
+		let encoder = ENCODERS.iter().find(|(n, _)| *n == name).map(|(_, c)| c);
+
+		match known.or(encoder) {
+			Some(codec) => codec.clone(),
+			None => Self::Unknown(String::from(name)),
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		match self {
+			Self::Unknown(name) => name,
+			codec => NAMES.iter().find(|(c, _)| c == codec).unwrap().1,
+		}
+	}
+}
+
+impl fmt::Display for Codec {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
@@ -0,0 +1,115 @@
+use std::path::Path;
+
+use argparse::{ArgumentParser, List, StoreTrue};
+
+use crate::{
+	error::{Error, Result},
+	logging,
+};
+
+use super::Options;
+
+pub fn run(options: &Options, args: Vec<String>) -> i32 {
+	let mut all = false;
+	let mut names = Vec::<String>::new();
+
+	{
+		let mut parser = ArgumentParser::new();
+		parser.set_description(
+			"Remove working directories of items that were written to the output directory",
+		);
+		parser.refer(&mut all).add_option(
+			&["--all"],
+			StoreTrue,
+			"Remove all working directories, including unfinished items",
+		);
+		parser
+			.refer(&mut names)
+			.add_argument("inputs", List, "Only clean these input directories");
+
+		if let Err(code) = super::parse(&parser, args) {
+			return code;
+		}
+	}
+
+	if !options.working.exists() {
+		return 0;
+	}
+
+	let working = super::report(super::existing(&options.working));
+	if working.is_none() {
+		return 1;
+	}
+
+	let working = working.unwrap();
+
+	let dirs = super::report(std::fs::read_dir(&working).map_err(|e| Error::io(&working, e)));
+	if dirs.is_none() {
+		return 1;
+	}
+
+	let mut dirs = dirs
+		.unwrap()
+		.filter_map(|e| e.ok().map(|e| e.path()))
+		.filter(|p| p.is_dir())
+		.collect::<Vec<_>>();
+
+	dirs.sort();
+
+	for dir in dirs {
+		let name = dir.file_name().unwrap().to_str().unwrap().to_string();
+		if !names.is_empty() && !names.contains(&name) {
+			continue;
+		}
+
+		let output = options.output.join(&name);
+		let err = logging::scope("config", &name, || super::report(clean(&dir, &output, all)));
+		if err.is_none() {
+			return 1;
+		}
+	}
+
+	0
+}
+
+fn clean(working: &Path, output: &Path, all: bool) -> Result<()> {
+	if all {
+		return remove(working);
+	}
+
+	let items = std::fs::read_dir(working).map_err(|e| Error::io(working, e))?;
+
+	for item in items {
+		let path = item.map_err(|e| Error::io(working, e))?.path();
+		if !path.is_dir() {
+			continue;
+		}
+
+		let name = path.file_name().unwrap().to_str().unwrap();
+		if output.join(format!("{name}.mkv")).exists() || written(&path) {
+			remove(&path)?;
+			continue;
+		}
+
+		// Folders of the input like seasons are mirrored in the output, their items are inside
+		if output.join(name).is_dir() {
+			clean(&path, &output.join(name), false)?;
+		}
+	}
+
+	Ok(())
+}
+
+// Outputs named by a template are recorded next to the fingerprint of the item
+fn written(working: &Path) -> bool {
+	let name = working.file_name().unwrap().to_str().unwrap();
+	let record = std::fs::read_to_string(working.with_file_name(format!("{name}.fingerprint")));
+
+	record.is_ok_and(|r| r.lines().nth(1).is_some_and(|o| Path::new(o).exists()))
+}
+
+fn remove(path: &Path) -> Result<()> {
+	logging::info!("Removing {}", path.to_str().unwrap());
+
+	std::fs::remove_dir_all(path).map_err(|e| Error::io(path, e).context("remove directory"))
+}
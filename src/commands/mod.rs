@@ -0,0 +1,76 @@
+mod clean;
+mod probe;
+mod process;
+mod select;
+mod validate;
+
+use std::{
+	io::{self, stderr, stdout},
+	path::{Path, PathBuf},
+};
+
+use argparse::ArgumentParser;
+
+use crate::{
+	error::{Error, Result},
+	logging,
+};
+
+pub struct Options {
+	pub config: PathBuf,
+	pub input: PathBuf,
+	pub working: PathBuf,
+	pub output: PathBuf,
+}
+
+pub const COMMANDS: &str = "process, probe, select, validate, clean";
+
+pub fn run(command: &str, options: &Options, mut args: Vec<String>) -> i32 {
+	args.insert(0, format!("videoproc {command}"));
+
+	match command {
+		"process" => process::run(options, args),
+		"probe" => probe::run(options, args),
+		"select" => select::run(options, args),
+		"validate" => validate::run(options, args),
+		"clean" => clean::run(options, args),
+		_ => {
+			logging::error!("Unknown command {}, expected one of {}", command, COMMANDS);
+			1
+		}
+	}
+}
+
+fn parse(parser: &ArgumentParser, args: Vec<String>) -> std::result::Result<(), i32> {
+	parser.parse(args, &mut stdout(), &mut stderr())
+}
+
+// Logs the error of a command, which then exits with a failure
+fn report<T>(result: Result<T>) -> Option<T> {
+	match result {
+		Ok(value) => Some(value),
+		Err(err) => {
+			logging::error!("{}", err);
+			None
+		}
+	}
+}
+
+fn existing(path: &Path) -> Result<PathBuf> {
+	if !path.is_dir() {
+		let err = io::Error::new(io::ErrorKind::NotADirectory, "not a directory");
+		return Err(Error::io(path, err));
+	}
+
+	path.canonicalize().map_err(|e| Error::io(path, e))
+}
+
+fn create(path: &Path) -> Result<PathBuf> {
+	if path.exists() && !path.is_dir() {
+		let err = io::Error::new(io::ErrorKind::NotADirectory, "not a directory");
+		return Err(Error::io(path, err));
+	}
+
+	std::fs::create_dir_all(path).map_err(|e| Error::io(path, e).context("create directory"))?;
+	path.canonicalize().map_err(|e| Error::io(path, e))
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use argparse::{ArgumentParser, Store};
+
+use crate::{codec::Codec, logging, mkv, tools};
+
+use super::Options;
+
+pub fn run(_options: &Options, args: Vec<String>) -> i32 {
+	let mut file = PathBuf::new();
+
+	{
+		let mut parser = ArgumentParser::new();
+		parser.set_description("Print the streams of a file");
+		parser
+			.refer(&mut file)
+			.required()
+			.add_argument("file", Store, "File to probe");
+
+		if let Err(code) = super::parse(&parser, args) {
+			return code;
+		}
+	}
+
+	if !tools::check(&[tools::FFPROBE, tools::MEDIAINFO]) {
+		return 1;
+	}
+
+	let streams = mkv::streams(&vec![file], None);
+	if let Err(err) = streams {
+		logging::error!("{}", err);
+		return 1;
+	}
+
+	for stream in streams.unwrap() {
+		logging::info!("Stream {} #{}", stream.id, stream.index);
+		logging::info!("  type: {}", stream.streamtype);
+		logging::info!(
+			"  codec: {}",
+			stream.codec.as_ref().map_or("None", Codec::name)
+		);
+		logging::info!(
+			"  language: {}",
+			stream.language.as_deref().unwrap_or("None")
+		);
+		logging::info!("  offset: {}s", stream.offset);
+		logging::info!("  duration: {}s", stream.duration);
+
+		if let Some(aspect) = &stream.aspect {
+			logging::info!("  aspect: {}", aspect);
+		}
+
+		if let Some(framerate) = stream.framerate {
+			logging::info!("  framerate: {}/{}", framerate.0, framerate.1);
+		}
+
+		if let Some(samplerate) = stream.samplerate {
+			logging::info!("  samplerate: {}", samplerate);
+		}
+
+		if let Some(channels) = stream.channels {
+			logging::info!("  channels: {}", channels);
+		}
+
+		if let Some(dialnorm) = stream.dialnorm {
+			logging::info!("  dialnorm: {}", dialnorm);
+		}
+
+		if let Some(dsurmode) = stream.dsurmode {
+			logging::info!("  dsurmode: {}", dsurmode);
+		}
+	}
+
+	0
+}
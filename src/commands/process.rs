@@ -0,0 +1,184 @@
+use argparse::{ArgumentParser, Store, StoreTrue};
+
+use crate::{
+	config, filters,
+	input::{self, Report, Status},
+	logging, mkv,
+	scheduler::Scheduler,
+	tools::{self, Tool},
+};
+
+use super::Options;
+
+pub fn run(options: &Options, args: Vec<String>) -> i32 {
+	let mut dryrun = false;
+	let mut keepgoing = false;
+	let mut jobs = 1;
+	let mut video = 1;
+	let mut audio = 4;
+
+	{
+		let mut parser = ArgumentParser::new();
+		parser.set_description("Process all inputs that have a configuration");
+		parser.refer(&mut dryrun).add_option(
+			&["--dry-run"],
+			StoreTrue,
+			"Print the processing plan without running it",
+		);
+		parser.refer(&mut keepgoing).add_option(
+			&["-k", "--keep-going"],
+			StoreTrue,
+			"Continue with the next item if an item fails",
+		);
+		parser.refer(&mut jobs).add_option(
+			&["-j", "--jobs"],
+			Store,
+			"Number of jobs that run at the same time",
+		);
+		parser.refer(&mut video).add_option(
+			&["--video-jobs"],
+			Store,
+			"Number of video streams that are processed at the same time",
+		);
+		parser.refer(&mut audio).add_option(
+			&["--audio-jobs"],
+			Store,
+			"Number of audio streams that are processed at the same time",
+		);
+
+		if let Err(code) = super::parse(&parser, args) {
+			return code;
+		}
+	}
+
+	/*
+	 * Check if directories exist
+	 */
+
+	let config = super::report(super::existing(&options.config));
+	let input = super::report(super::existing(&options.input));
+	if config.is_none() || input.is_none() {
+		return 1;
+	}
+
+	/*
+	 * Create working and output directories
+	 */
+
+	let working = super::report(super::create(&options.working));
+	let output = super::report(super::create(&options.output));
+	if working.is_none() || output.is_none() {
+		return 1;
+	}
+
+	let config = config.unwrap();
+	let input = input.unwrap();
+	let working = working.unwrap();
+	let output = output.unwrap();
+
+	// Invalid configurations are reported before anything runs
+	let cfgs = config::load(&config);
+	if let Err(err) = cfgs {
+		logging::error!("{}", err);
+		return 1;
+	}
+
+	let cfgs = cfgs.unwrap();
+
+	/*
+	 * Test the commandline programs required by the configurations
+	 */
+
+	let mut required = Vec::<Tool>::new();
+	tools::add(&mut required, mkv::TOOLS);
+
+	// Overrides can add filters, so their tools are required as well
+	for cfg in cfgs
+		.values()
+		.flat_map(|c| std::iter::once(c).chain(c.variants.iter().map(|v| &v.1)))
+	{
+		for entry in &cfg.streams {
+			for option in &entry.options {
+				tools::add(&mut required, &filters::tools(option));
+			}
+		}
+	}
+
+	if !tools::check(&required) {
+		return 1;
+	}
+
+	// The plan is printed item by item, so it can't run in parallel
+	if dryrun {
+		jobs = 1;
+	}
+
+	// Process inputs
+	let scheduler = Scheduler::new(jobs, video, audio);
+	let reports = input::process(
+		&cfgs, &input, &working, &output, dryrun, keepgoing, &scheduler,
+	);
+
+	if let Err(err) = reports {
+		logging::error!("{}", err);
+		return 1;
+	}
+
+	let reports = reports.unwrap();
+	summary(&reports, dryrun);
+
+	// Let wrappers know that some items failed
+	let failed = reports
+		.iter()
+		.any(|r| matches!(r.status, Status::Failed(_) | Status::Aborted));
+
+	if failed {
+		return 2;
+	}
+
+	0
+}
+
+fn summary(reports: &[Report], dryrun: bool) {
+	if reports.is_empty() {
+		return;
+	}
+
+	let names = reports
+		.iter()
+		.map(|r| {
+			if r.item.is_empty() {
+				r.dir.clone()
+			} else {
+				format!("{}/{}", r.dir, r.item)
+			}
+		})
+		.collect::<Vec<_>>();
+
+	let width = names.iter().map(|n| n.len()).max().unwrap_or_default();
+
+	logging::info!("Summary:");
+
+	for (name, report) in names.iter().zip(reports) {
+		let status = match &report.status {
+			Status::Processed if dryrun => String::from("planned"),
+			Status::Processed => String::from("succeeded"),
+			Status::Exists => String::from("skipped (output exists)"),
+			Status::Failed(err) => {
+				// The full error was already logged, only show its first line
+				let cause = err.cause().to_string();
+				let cause = cause.lines().next().unwrap_or_default();
+
+				let contexts = err.contexts();
+				if contexts.is_empty() {
+					format!("failed ({cause})")
+				} else {
+					format!("failed in {} ({})", contexts.join(", "), cause)
+				}
+			}
+			Status::Aborted => String::from("skipped (an earlier item failed)"),
+		};
+
+		logging::info!("  {:width$}  {}", name, status);
+	}
+}
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use argparse::{ArgumentParser, Store};
+
+use crate::{
+	config::{self, Builtins},
+	input, logging, mkv, select, tools,
+};
+
+use super::Options;
+
+pub fn run(options: &Options, args: Vec<String>) -> i32 {
+	let mut name = String::new();
+	let mut item = String::new();
+
+	{
+		let mut parser = ArgumentParser::new();
+		parser.set_description("Show which streams a configuration selects for an item");
+		parser.refer(&mut name).required().add_argument(
+			"config",
+			Store,
+			"Name of the configuration",
+		);
+		parser.refer(&mut item).required().add_argument(
+			"item",
+			Store,
+			"Name of the item in the input directory, or <directory>/<item> for directories matched by patterns",
+		);
+
+		if let Err(code) = super::parse(&parser, args) {
+			return code;
+		}
+	}
+
+	let config = super::report(super::existing(&options.config));
+	let input = super::report(super::existing(&options.input));
+	if config.is_none() || input.is_none() {
+		return 1;
+	}
+
+	let config = config.unwrap();
+	let input = input.unwrap();
+
+	let file = match config::find(&config.join(&name)) {
+		Ok(Some(file)) => file,
+		Ok(None) => {
+			logging::error!("Configuration {} not found!", name);
+			return 1;
+		}
+		Err(err) => {
+			logging::error!("{}", err);
+			return 1;
+		}
+	};
+
+	let cfg = config::load_file(&config, &file);
+	if let Err(err) = cfg {
+		logging::error!("{}", err);
+		return 1;
+	}
+
+	// Items can be in folders of the input directory too, like Season 1/ep01
+	let own = locate(&input.join(&name), &item);
+	let (dir, item) = match item.split_once('/') {
+		Some(split) if own.is_none() => split,
+		_ => (name.as_str(), item.as_str()),
+	};
+
+	if cfg.as_ref().unwrap().matches(dir).is_none() {
+		logging::error!(
+			"Configuration {} does not match input directory {}!",
+			name,
+			dir
+		);
+		return 1;
+	}
+
+	let path = match locate(&input.join(dir), item) {
+		Some(path) => path,
+		None => {
+			let path = input.join(dir).join(item);
+			logging::error!("Item {} not found!", path.to_str().unwrap());
+			return 1;
+		}
+	};
+
+	let stem = path.file_stem().unwrap_or_default();
+	let item = Path::new(item).with_file_name(stem);
+	let working = options.working.join(dir).join(&item);
+	if !tools::check(&[tools::FFPROBE, tools::MEDIAINFO, tools::MKVMERGE]) {
+		return 1;
+	}
+
+	let builtins = Builtins {
+		item: item.to_str().unwrap(),
+		input: &path,
+		working: &working,
+	};
+
+	let cfg = cfg.unwrap().item(&builtins);
+	if let Err(err) = cfg {
+		logging::error!("{}", err);
+		return 1;
+	}
+
+	let cfg = cfg.unwrap();
+	let streams = input::item_files(&path, &cfg)
+		.and_then(|files| input::sources(&cfg, &files, &working))
+		.and_then(|files| mkv::streams(&files, None));
+
+	if let Err(err) = streams {
+		logging::error!("{}", err);
+		return 1;
+	}
+
+	select::explain(&cfg, &streams.unwrap());
+
+	0
+}
+
+// The media file or folder of an item, like a disc, files are named without their extension
+fn locate(dir: &Path, item: &str) -> Option<PathBuf> {
+	let path = dir.join(item);
+	if path.is_dir() || mkv::is_container(&path) {
+		return Some(path);
+	}
+
+	mkv::CONTAINERS
+		.iter()
+		.map(|ext| dir.join(format!("{item}.{ext}")))
+		.find(|p| mkv::is_container(p))
+}
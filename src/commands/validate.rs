@@ -0,0 +1,179 @@
+use std::{collections::BTreeMap, path::Path};
+
+use argparse::{ArgumentParser, StoreTrue};
+
+use crate::{
+	config::{self, Builtins, Config},
+	error::Result,
+	filters, input, logging, mkv, select, tools,
+};
+
+use super::Options;
+
+pub fn run(options: &Options, args: Vec<String>) -> i32 {
+	let mut probe = false;
+
+	{
+		let mut parser = ArgumentParser::new();
+		parser.set_description("Load and check all configurations");
+		parser.refer(&mut probe).add_option(
+			&["--probe"],
+			StoreTrue,
+			"Also probe the inputs and check that every stream entry finds a match",
+		);
+
+		if let Err(code) = super::parse(&parser, args) {
+			return code;
+		}
+	}
+
+	let config = super::report(super::existing(&options.config));
+	if config.is_none() {
+		return 1;
+	}
+
+	let config = config.unwrap();
+	let names = super::report(config::names(&config));
+	if names.is_none() {
+		return 1;
+	}
+
+	let mut cfgs = BTreeMap::<String, Config>::new();
+	let mut failed = 0;
+
+	for name in names.unwrap() {
+		let cfg = config::find(&config.join(&name))
+			.and_then(|p| config::load_file(&config, &p.unwrap()))
+			.and_then(|c| check(&c).map(|_| c));
+
+		if let Err(err) = cfg {
+			logging::error!("{}", err);
+			failed += 1;
+			continue;
+		}
+
+		let cfg = cfg.unwrap();
+		logging::info!("Configuration {} is valid", cfg.name);
+
+		cfgs.insert(cfg.name.clone(), cfg);
+	}
+
+	if failed > 0 {
+		logging::error!("{} configurations are invalid!", failed);
+	}
+
+	if probe {
+		let input = super::report(super::existing(&options.input));
+		let required = [tools::FFPROBE, tools::MEDIAINFO, tools::MKVMERGE];
+		if input.is_none() || !tools::check(&required) {
+			return 1;
+		}
+
+		let unmatched = probe_inputs(&cfgs, &input.unwrap(), &options.working);
+		if unmatched > 0 {
+			logging::error!(
+				"{} inputs have no configuration, could not be probed or have streams without a match!",
+				unmatched
+			);
+			failed += unmatched;
+		}
+	}
+
+	if failed > 0 {
+		return 1;
+	}
+
+	0
+}
+
+// Checks what loading a configuration can't, like the filter templates
+fn check(cfg: &Config) -> Result<()> {
+	let mut variants = vec![(String::new(), cfg)];
+
+	// Overrides can add filters of their own
+	for (glob, variant) in &cfg.variants {
+		variants.push((format!(" (override {glob})"), variant));
+	}
+
+	for (glob, variant) in variants {
+		for entry in &variant.streams {
+			for option in &entry.options {
+				filters::check(option).map_err(|e| {
+					e.context(format!("stream {}", entry.name))
+						.context(format!("configuration {}{}", cfg.name, glob))
+				})?;
+			}
+		}
+	}
+
+	Ok(())
+}
+
+// Selects the streams of every item like processing would, returns the number of failed items
+// and input directories without a configuration
+fn probe_inputs(cfgs: &BTreeMap<String, Config>, input: &Path, working: &Path) -> usize {
+	let dirs = input::dirs(input);
+	if let Err(err) = dirs {
+		logging::error!("{}", err);
+		return 1;
+	}
+
+	let mut failed = 0;
+
+	for dir in dirs.unwrap() {
+		let cfg = match config::assign(cfgs, &dir) {
+			Ok(Some(cfg)) => cfg,
+			Ok(None) => {
+				logging::error!("No configuration matches input directory {}", dir);
+				failed += 1;
+				continue;
+			}
+			Err(err) => {
+				logging::error!("{}", err);
+				failed += 1;
+				continue;
+			}
+		};
+
+		let items = input::items(&input.join(&dir), &cfg.layout);
+		if let Err(err) = items {
+			logging::error!("{}", err);
+			failed += 1;
+			continue;
+		}
+
+		for (name, path) in items.unwrap() {
+			let wdir = working.join(&dir).join(&name);
+
+			let builtins = Builtins {
+				item: &name,
+				input: &path,
+				working: &wdir,
+			};
+
+			// Filter templates can be named after the item
+			let result = cfg
+				.item(&builtins)
+				.map_err(|e| e.context("configuration"))
+				.and_then(|cfg| check(&cfg).map(|_| cfg))
+				.and_then(|cfg| {
+					let files = input::item_files(&path, &cfg)
+						.and_then(|files| input::sources(&cfg, &files, &wdir))
+						.map_err(|e| e.context("title"))?;
+					let streams = mkv::streams(&files, None).map_err(|e| e.context("probe"))?;
+
+					select::find(&cfg, &streams).map_err(|e| e.context("select"))
+				});
+
+			match result {
+				Ok(_) => logging::info!("All streams of {}/{} have a match", dir, name),
+				Err(err) => {
+					logging::error!("{}/{}: {}", dir, name, err);
+					failed += 1;
+				}
+			}
+		}
+	}
+
+	failed
+}
@@ -0,0 +1,255 @@
+use std::path::Path;
+
+use json::{object, JsonValue};
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::error::{Error, Result};
+
+use super::resolve;
+
+// Supported configuration formats, in the order they are looked up
+pub const EXTENSIONS: &[&str] = &["json", "yaml", "yml", "toml"];
+
+// Reads a configuration file of any supported format into the same structure
+pub fn read(path: &Path) -> Result<JsonValue> {
+	let data = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+	let ext = path
+		.extension()
+		.unwrap_or_default()
+		.to_str()
+		.unwrap_or_default();
+
+	match ext {
+		"json" => json::parse(&uncomment(&data)).map_err(|e| Error::config(path, "", e)),
+		"yaml" | "yml" => {
+			let docs = YamlLoader::load_from_str(&data).map_err(|e| Error::config(path, "", e))?;
+
+			// An empty file has no documents
+			match docs.first() {
+				Some(doc) => from_yaml(path, doc, ""),
+				None => Ok(object! {}),
+			}
+		}
+		"toml" => {
+			let data = data
+				.parse::<toml::Value>()
+				.map_err(|e| Error::config(path, "", e))?;
+
+			Ok(from_toml(&data))
+		}
+		_ => Err(Error::config(path, "", "unknown configuration format")),
+	}
+}
+
+// Removes // and /* */ comments outside of strings, so JSON files can explain themselves
+fn uncomment(data: &str) -> String {
+	let mut result = String::with_capacity(data.len());
+	let mut chars = data.chars().peekable();
+	let mut string = false;
+
+	while let Some(c) = chars.next() {
+		if string {
+			result.push(c);
+
+			if c == '\\' {
+				if let Some(next) = chars.next() {
+					result.push(next);
+				}
+			} else if c == '"' {
+				string = false;
+			}
+
+			continue;
+		}
+
+		match (c, chars.peek()) {
+			('"', _) => {
+				string = true;
+				result.push(c);
+			}
+			('/', Some('/')) => {
+				// Keep the newline so error positions still match the file
+				while chars.next_if(|c| *c != '\n').is_some() {}
+			}
+			('/', Some('*')) => {
+				chars.next();
+
+				let mut prev = ' ';
+				for c in chars.by_ref() {
+					if c == '\n' {
+						result.push(c);
+					}
+
+					if prev == '*' && c == '/' {
+						break;
+					}
+
+					prev = c;
+				}
+			}
+			_ => result.push(c),
+		}
+	}
+
+	result
+}
+
+fn from_yaml(file: &Path, value: &Yaml, path: &str) -> Result<JsonValue> {
+	let value = match value {
+		Yaml::Null => JsonValue::Null,
+		Yaml::Boolean(value) => JsonValue::from(*value),
+		Yaml::Integer(value) => JsonValue::from(*value),
+		Yaml::Real(value) => match value.parse::<f64>() {
+			Ok(value) => JsonValue::from(value),
+			Err(_) => JsonValue::from(value.as_str()),
+		},
+		Yaml::String(value) => JsonValue::from(value.as_str()),
+		Yaml::Array(values) => {
+			let mut new = Vec::<JsonValue>::new();
+
+			for (i, value) in values.iter().enumerate() {
+				new.push(from_yaml(file, value, &format!("{path}[{i}]"))?);
+			}
+
+			JsonValue::Array(new)
+		}
+		Yaml::Hash(values) => {
+			let mut new = object! {};
+
+			for (key, value) in values {
+				// Numbers and booleans are valid keys in YAML, use them as written
+				let key = match key {
+					Yaml::String(key) | Yaml::Real(key) => key.clone(),
+					Yaml::Integer(key) => key.to_string(),
+					Yaml::Boolean(key) => key.to_string(),
+					_ => return Err(Error::config(file, path, "expected string keys")),
+				};
+
+				new[key] = from_yaml(file, value, &resolve::child(path, &key))?;
+			}
+
+			new
+		}
+		Yaml::Alias(_) | Yaml::BadValue => {
+			return Err(Error::config(file, path, "unsupported value"));
+		}
+	};
+
+	Ok(value)
+}
+
+fn from_toml(value: &toml::Value) -> JsonValue {
+	match value {
+		toml::Value::String(value) => JsonValue::from(value.as_str()),
+		toml::Value::Integer(value) => JsonValue::from(*value),
+		toml::Value::Float(value) => JsonValue::from(*value),
+		toml::Value::Boolean(value) => JsonValue::from(*value),
+		toml::Value::Datetime(value) => JsonValue::from(value.to_string()),
+		toml::Value::Array(values) => JsonValue::Array(values.iter().map(from_toml).collect()),
+		toml::Value::Table(values) => {
+			let mut new = object! {};
+
+			for (key, value) in values {
+				new[key.as_str()] = from_toml(value);
+			}
+
+			new
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use tempdir::TempDir;
+
+	use super::*;
+
+	fn load(name: &str, data: &str) -> Result<JsonValue> {
+		let dir = TempDir::new("videoproc").unwrap();
+		let path = dir.path().join(name);
+
+		std::fs::write(&path, data).unwrap();
+		read(&path)
+	}
+
+	#[test]
+	fn comments() {
+		let data = "{\n\t// The show\n\t\"a\": 1, /* inline */ \"b\": 2\n}";
+		assert_eq!(uncomment(data), "{\n\t\n\t\"a\": 1,  \"b\": 2\n}");
+
+		// Block comments keep their newlines, so errors point at the right line
+		assert_eq!(uncomment("1 /* a\nb */ 2"), "1 \n 2");
+
+		// Comments inside strings are part of the string
+		let data = r#"{"url": "http://example.com", "glob": "/* x */"}"#;
+		assert_eq!(uncomment(data), data);
+
+		// An escaped quote doesn't end the string
+		let data = r#"{"a": "say \"// hi\"", "b": "\\"} // end"#;
+		assert_eq!(uncomment(data), r#"{"a": "say \"// hi\"", "b": "\\"} "#);
+		assert_eq!(json::parse(&uncomment(data)).unwrap()["a"], "say \"// hi\"");
+	}
+
+	#[test]
+	fn formats() {
+		let json = r#"{
+			// Comments are allowed
+			"title": "longest",
+			"join": true,
+			"video": [{"type": "video", "filters": [{"$type": "speed", "framerate": 25, "rate": 23.976}]}],
+			"overrides": {"ep01": {"title": 2}}
+		}"#;
+
+		let yaml = "
+# Comments are allowed
+title: longest
+join: true
+video:
+  - type: video
+    filters:
+      - $type: speed
+        framerate: 25
+        rate: 23.976
+overrides:
+  ep01:
+    title: 2
+";
+
+		let toml = r#"
+# Comments are allowed
+title = "longest"
+join = true
+
+[[video]]
+type = "video"
+filters = [{ "$type" = "speed", framerate = 25, rate = 23.976 }]
+
+[overrides.ep01]
+title = 2
+"#;
+
+		let json = load("show.json", json).unwrap();
+
+		assert_eq!(load("show.yaml", yaml).unwrap(), json);
+		assert_eq!(load("show.toml", toml).unwrap(), json);
+
+		// Key order is kept, it decides the order of the streams
+		let keys = json.entries().map(|(k, _)| k).collect::<Vec<_>>();
+		assert_eq!(keys, ["title", "join", "video", "overrides"]);
+	}
+
+	#[test]
+	fn yaml() {
+		assert_eq!(load("empty.yaml", "").unwrap(), object! {});
+		assert_eq!(
+			load("keys.yaml", "1: a\ntrue: b").unwrap(),
+			object! { "1": "a", "true": "b" }
+		);
+
+		let err = load("keys.yaml", "a:\n  [1]: x").unwrap_err().to_string();
+		assert!(err.ends_with("a: expected string keys"), "{err}");
+
+		let err = load("show.txt", "").unwrap_err().to_string();
+		assert!(err.ends_with("unknown configuration format"), "{err}");
+	}
+}
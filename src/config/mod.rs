@@ -0,0 +1,303 @@
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+};
+
+use json::JsonValue;
+use regex::Regex;
+
+use crate::{
+	error::{Error, Result},
+	logging,
+};
+
+mod format;
+mod model;
+mod resolve;
+mod vars;
+
+pub use format::read;
+pub use model::*;
+pub use vars::Builtins;
+
+// Global settings, not an item configuration, in any of the supported formats
+pub const SETTINGS: &str = "_settings";
+
+// Fails if any configuration is invalid, after reporting all of them
+pub fn load(config: &Path) -> Result<BTreeMap<String, Config>> {
+	let mut result = BTreeMap::<String, Config>::new();
+	let mut failed = 0;
+
+	for name in names(config)? {
+		let cfg = find(&config.join(&name)).and_then(|p| load_file(config, &p.unwrap()));
+		if let Err(err) = cfg {
+			logging::error!("{}", err);
+			failed += 1;
+			continue;
+		}
+
+		let cfg = cfg.unwrap();
+		result.insert(cfg.name.clone(), cfg);
+	}
+
+	if failed > 0 {
+		let reason = format!("{failed} configurations are invalid");
+		return Err(Error::config(config, "", reason));
+	}
+
+	Ok(result)
+}
+
+// The configuration that processes an input folder, the most specific match wins
+pub fn assign<'a>(cfgs: &'a BTreeMap<String, Config>, dir: &str) -> Result<Option<&'a Config>> {
+	let matches = cfgs
+		.values()
+		.filter_map(|c| c.matches(dir).map(|s| (s, c)))
+		.collect::<Vec<_>>();
+
+	let best = matches.iter().map(|m| m.0).max();
+	let best = matches
+		.iter()
+		.filter(|m| Some(m.0) == best)
+		.map(|m| m.1)
+		.collect::<Vec<_>>();
+
+	if best.len() > 1 {
+		return Err(Error::Ambiguous {
+			dir: String::from(dir),
+			configs: best.iter().map(|c| c.name.clone()).collect(),
+		});
+	}
+
+	Ok(best.first().copied())
+}
+
+// Names of all configurations, a name may have files in multiple formats
+pub fn names(config: &Path) -> Result<Vec<String>> {
+	let mut names = Vec::<String>::new();
+
+	for entry in config.read_dir().map_err(|e| Error::io(config, e))? {
+		let path = entry.map_err(|e| Error::io(config, e))?.path();
+
+		if !path.is_file() {
+			continue;
+		}
+
+		let ext = path.extension().unwrap_or_default().to_str();
+		if !format::EXTENSIONS.contains(&ext.unwrap_or_default()) {
+			continue;
+		}
+
+		let name = path.file_stem().unwrap_or_default().to_str();
+		if name.is_none() || name == Some(SETTINGS) {
+			continue;
+		}
+
+		names.push(String::from(name.unwrap()));
+	}
+
+	names.sort();
+	names.dedup();
+	Ok(names)
+}
+
+// Finds the file for a path without extension, fails if it exists in more than one format
+pub fn find(base: &Path) -> Result<Option<PathBuf>> {
+	let name = base.file_name().unwrap_or_default().to_str().unwrap();
+
+	let found = format::EXTENSIONS
+		.iter()
+		.map(|ext| base.with_file_name(format!("{name}.{ext}")))
+		.filter(|p| p.is_file())
+		.collect::<Vec<_>>();
+
+	if found.len() > 1 {
+		let files = found
+			.iter()
+			.map(|p| p.file_name().unwrap().to_str().unwrap())
+			.collect::<Vec<_>>();
+
+		let reason = format!("defined in multiple files: {}", files.join(", "));
+		return Err(Error::config(base, "", reason));
+	}
+
+	Ok(found.into_iter().next())
+}
+
+pub fn load_file(config: &Path, path: &Path) -> Result<Config> {
+	let data = resolve::file(config, path)?;
+
+	let name = path.file_stem().unwrap_or_default().to_str();
+	if name.is_none() {
+		return Err(Error::config(path, "", "invalid configuration name"));
+	}
+
+	build(path, name.unwrap(), data, None)
+}
+
+impl Config {
+	// The configuration of a single item, with the per-item builtins filled in
+	pub fn item(&self, builtins: &Builtins) -> Result<Config> {
+		build(&self.file, &self.name, self.source.clone(), Some(builtins))
+	}
+
+	pub fn file(&self) -> &Path {
+		&self.file
+	}
+
+	// The resolved JSON of everything that changes the outputs, without the keys that find the files
+	pub fn settings(&self) -> String {
+		let mut data = self.resolved.clone();
+
+		for key in ["match", "title", "layout"] {
+			data.remove(key);
+		}
+
+		data.dump()
+	}
+}
+
+fn build(path: &Path, name: &str, data: JsonValue, builtins: Option<&Builtins>) -> Result<Config> {
+	let source = data.clone();
+	let mut data = data;
+	let mut variants = Vec::<(String, Config)>::new();
+
+	let overrides = overrides(path, data.remove("overrides"))?;
+
+	match builtins {
+		// Overrides are applied in the order they are written, so later ones win
+		Some(builtins) => {
+			let stem = builtins.item.rsplit('/').next().unwrap_or_default();
+
+			for (_, regex, changes) in overrides {
+				if regex.is_match(builtins.item) || regex.is_match(stem) {
+					data = resolve::merge(data, changes);
+				}
+			}
+
+			data = resolve::finish(data);
+		}
+		None => {
+			for (glob, _, changes) in overrides {
+				let variant = resolve::finish(resolve::merge(data.clone(), changes));
+				let variant = vars::interpolate(path, &variant, None)
+					.and_then(|v| parse(path, name, &v))
+					.map_err(|e| e.context(format!("override {glob}")))?;
+
+				variants.push((glob, variant));
+			}
+		}
+	}
+
+	let resolved = vars::interpolate(path, &data, builtins)?;
+	let mut cfg = parse(path, name, &resolved)?;
+
+	cfg.variants = variants;
+	cfg.source = source;
+	cfg.resolved = resolved;
+	Ok(cfg)
+}
+
+// Changes for the items matching a glob on the name of the item, or its path in the input folder
+fn overrides(path: &Path, data: JsonValue) -> Result<Vec<(String, Regex, JsonValue)>> {
+	let mut result = Vec::new();
+
+	if data.is_null() {
+		return Ok(result);
+	}
+
+	if !data.is_object() {
+		return Err(Error::config(
+			path,
+			"overrides",
+			"expected an object of item globs",
+		));
+	}
+
+	for (glob, changes) in data.entries() {
+		let key = format!("overrides.{glob}");
+
+		let regex = fnmatch_regex::glob_to_regex(glob);
+		if let Err(err) = regex {
+			return Err(Error::config(path, &key, format!("invalid glob: {err}")));
+		}
+
+		if !changes.is_object() {
+			return Err(Error::config(path, &key, "expected an object"));
+		}
+
+		result.push((String::from(glob), regex.unwrap(), changes.clone()));
+	}
+
+	Ok(result)
+}
+
+fn load_dict(path: &Path) -> Result<JsonValue> {
+	let data = format::read(path)?;
+
+	if !data.is_object() {
+		return Err(Error::config(path, "", "expected an object"));
+	}
+
+	Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+	use json::object;
+
+	use super::*;
+
+	fn configs(matches: &[(&str, &[&str])]) -> BTreeMap<String, Config> {
+		let mut cfgs = BTreeMap::new();
+
+		for (name, patterns) in matches {
+			let data = object! { match: *patterns };
+			let cfg = model::parse(Path::new("test.json"), name, &data).unwrap();
+
+			cfgs.insert(String::from(*name), cfg);
+		}
+
+		cfgs
+	}
+
+	fn assigned(cfgs: &BTreeMap<String, Config>, dir: &str) -> Option<String> {
+		assign(cfgs, dir).unwrap().map(|c| c.name.clone())
+	}
+
+	#[test]
+	fn specificity() {
+		let cfgs = configs(&[
+			("anime", &["*"]),
+			("show", &["Show*"]),
+			("regex", &["re:Show.*"]),
+			("Show S2", &[]),
+		]);
+
+		// More literal characters win, any glob wins over a regex
+		assert_eq!(assigned(&cfgs, "Movie").as_deref(), Some("anime"));
+		assert_eq!(assigned(&cfgs, "Show S1").as_deref(), Some("show"));
+
+		// The folder named like a configuration always belongs to it
+		assert_eq!(cfgs["Show S2"].matches("Show S2"), Some(usize::MAX));
+		assert_eq!(assigned(&cfgs, "Show S2").as_deref(), Some("Show S2"));
+
+		let cfgs = configs(&[("show", &["Show*"])]);
+		assert_eq!(assigned(&cfgs, "Movie"), None);
+	}
+
+	#[test]
+	fn ambiguous() {
+		let cfgs = configs(&[("a", &["Show ?"]), ("b", &["Sh*w 1"]), ("c", &["*"])]);
+
+		match assign(&cfgs, "Show 1") {
+			Err(Error::Ambiguous { dir, configs }) => {
+				assert_eq!(dir, "Show 1");
+				assert_eq!(configs, ["a", "b"]);
+			}
+			_ => panic!("expected an ambiguous match"),
+		}
+
+		assert_eq!(assigned(&cfgs, "Show 2").as_deref(), Some("a"));
+	}
+}
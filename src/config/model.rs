@@ -0,0 +1,725 @@
+use std::{
+	fmt,
+	path::{Path, PathBuf},
+};
+
+use json::JsonValue;
+use regex::Regex;
+
+use crate::{
+	error::{Error, Result},
+	mkv::StreamType,
+	naming,
+	timestamp::Timestamp,
+	utils,
+};
+
+use super::resolve;
+
+// Top level keys that are not stream entries
+const RESERVED: &[&str] = &["match", "overrides", "title", "layout", "output", "join"];
+
+// Selects and processes the streams of all items in the input folders it matches
+#[derive(Debug)]
+pub struct Config {
+	pub name: String,
+	pub streams: Vec<Entry>,
+
+	// Input folders besides the one of the same name, as globs or regexes starting with re:
+	pub patterns: Vec<Pattern>,
+
+	// The title of DVD and Blu-ray items to process
+	pub title: Title,
+
+	// Where the items are in the input folder
+	pub layout: Layout,
+
+	// Names the outputs, instead of using the name of the item
+	pub output: Option<Output>,
+
+	// How the files of an item are combined
+	pub join: Join,
+
+	// The configuration as it is for the items of each override, to check them up front
+	pub variants: Vec<(String, Config)>,
+
+	// The resolved configuration before interpolation, to fill in the variables of each item
+	pub(super) file: PathBuf,
+	pub(super) source: JsonValue,
+
+	// The configuration after overrides and variables, which the outputs are fingerprinted with
+	pub(super) resolved: JsonValue,
+}
+
+// A stream of the output, the first option that matches an input stream is used
+#[derive(Debug)]
+pub struct Entry {
+	pub name: String,
+	pub options: Vec<Selector>,
+}
+
+#[derive(Debug)]
+pub struct Pattern {
+	regex: Regex,
+
+	// Number of literal characters, a more specific pattern takes precedence
+	specificity: usize,
+}
+
+impl Pattern {
+	pub fn is_match(&self, name: &str) -> bool {
+		self.regex.is_match(name)
+	}
+}
+
+#[derive(Debug)]
+pub struct Output {
+	// Path relative to the output directory without the extension, like {input}/{item} [{resolution}]
+	pub template: String,
+
+	// Matched against the item, its named groups like season and episode are placeholders too
+	pub pattern: Regex,
+}
+
+#[derive(Debug, Default)]
+pub enum Join {
+	// Every file is a source of streams, like a video and a separate audio file
+	#[default]
+	Parallel,
+
+	// The files are parts of one timeline, in the order of the globs they match or by name
+	Concat(Vec<Regex>),
+}
+
+#[derive(Debug, Default)]
+pub enum Layout {
+	// Every media file and directory in the input folder is an item
+	#[default]
+	Flat,
+
+	// Every media file at any depth
+	Files,
+
+	// Every directory at any depth that contains media files, or a disc
+	Directories,
+
+	// A glob for each level of folders, the last one matches the items
+	Pattern(Vec<Regex>),
+}
+
+#[derive(Debug, Default)]
+pub enum Title {
+	#[default]
+	Longest,
+
+	// Position of the title on the disc, starting at 1
+	Index(usize),
+
+	// The longest title with a duration between min and max seconds
+	Duration {
+		min: Option<Timestamp>,
+		max: Option<Timestamp>,
+	},
+}
+
+#[derive(Clone, Debug)]
+pub struct Selector {
+	pub streamtype: Option<StreamType>,
+	pub lang: Option<String>,
+	pub id: Option<Vec<String>>,
+	pub file: Option<Vec<String>>,
+
+	// Only matches if no input has the stream, which makes the stream optional
+	pub missing: bool,
+
+	pub filters: Vec<Filter>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Filter {
+	// All options besides the codec are passed to ffmpeg as -<key> <value>
+	Encode {
+		codec: String,
+		options: Vec<(String, String)>,
+	},
+
+	// Globs matched against the working directory, and the offset in seconds
+	Offset {
+		offsets: Vec<(String, Timestamp)>,
+	},
+
+	VapourSynth {
+		filter: PathBuf,
+	},
+
+	AviSynth {
+		filter: PathBuf,
+	},
+
+	// Video streams use the framerate, all other streams the input and output framerates
+	Speed {
+		framerate: Option<(u32, u32)>,
+		input: Option<(u32, u32)>,
+		output: Option<(u32, u32)>,
+	},
+
+	Tempo {
+		input: (u32, u32),
+		output: (u32, u32),
+	},
+
+	Pitch {
+		input: (u32, u32),
+		output: (u32, u32),
+	},
+}
+
+impl Config {
+	// How well the configuration matches an input folder, higher is better
+	pub fn matches(&self, dir: &str) -> Option<usize> {
+		// The folder of the same name always comes first
+		if self.name == dir {
+			return Some(usize::MAX);
+		}
+
+		self.patterns
+			.iter()
+			.filter(|p| p.is_match(dir))
+			.map(|p| p.specificity)
+			.max()
+	}
+}
+
+impl fmt::Display for Title {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Longest => write!(f, "longest"),
+			Self::Index(i) => write!(f, "title {i}"),
+			Self::Duration { min, max } => {
+				let min = min.map(|m| format!("{m}s")).unwrap_or_default();
+				let max = max.map(|m| format!("{m}s")).unwrap_or_default();
+
+				write!(f, "duration {min}..{max}")
+			}
+		}
+	}
+}
+
+impl Filter {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Encode { .. } => "encode",
+			Self::Offset { .. } => "offset",
+			Self::VapourSynth { .. } => "vapoursynth",
+			Self::AviSynth { .. } => "avisynth",
+			Self::Speed { .. } => "speed",
+			Self::Tempo { .. } => "tempo",
+			Self::Pitch { .. } => "pitch",
+		}
+	}
+}
+
+pub fn parse(file: &Path, name: &str, data: &JsonValue) -> Result<Config> {
+	let parser = Parser { file };
+	let mut streams = Vec::<Entry>::new();
+
+	for (key, value) in data.entries() {
+		if RESERVED.contains(&key) {
+			continue;
+		}
+
+		if !value.is_array() {
+			return Err(parser.error(key, "expected a list of options"));
+		}
+
+		let mut options = Vec::<Selector>::new();
+
+		for (i, option) in value.members().enumerate() {
+			options.push(parser.selector(option, &format!("{key}[{i}]"))?);
+		}
+
+		streams.push(Entry {
+			name: String::from(key),
+			options,
+		});
+	}
+
+	let patterns = parser.optional(data, "", "match", |v, p| {
+		parser
+			.strings(v, p)?
+			.into_iter()
+			.enumerate()
+			.map(|(i, s)| parser.pattern(s, &format!("{p}[{i}]")))
+			.collect::<Result<Vec<_>>>()
+	})?;
+
+	let title = parser.optional(data, "", "title", |v, p| parser.title(v, p))?;
+	let layout = parser.optional(data, "", "layout", |v, p| parser.layout(v, p))?;
+	let output = parser.optional(data, "", "output", |v, p| parser.output(v, p))?;
+	let join = parser.optional(data, "", "join", |v, p| parser.join(v, p))?;
+
+	Ok(Config {
+		name: String::from(name),
+		streams,
+		patterns: patterns.unwrap_or_default(),
+		title: title.unwrap_or_default(),
+		layout: layout.unwrap_or_default(),
+		output,
+		join: join.unwrap_or_default(),
+		variants: Vec::new(),
+		file: file.to_path_buf(),
+		source: JsonValue::Null,
+		resolved: JsonValue::Null,
+	})
+}
+
+struct Parser<'a> {
+	file: &'a Path,
+}
+
+impl Parser<'_> {
+	fn error(&self, path: &str, reason: impl fmt::Display) -> Error {
+		Error::config(self.file, path, reason)
+	}
+
+	fn selector(&self, data: &JsonValue, path: &str) -> Result<Selector> {
+		self.keys(
+			data,
+			path,
+			&["type", "lang", "id", "file", "missing", "filters"],
+		)?;
+
+		let streamtype = self.optional(data, path, "type", |v, p| {
+			let name = self.string(v, p)?;
+			StreamType::parse(&name)
+				.ok_or_else(|| self.error(p, format!("unknown stream type {name}")))
+		})?;
+
+		let missing = self.optional(data, path, "missing", |v, p| match v.as_bool() {
+			Some(value) => Ok(value),
+			None => Err(self.error(p, "expected true or false")),
+		})?;
+
+		let filters = self.optional(data, path, "filters", |v, p| {
+			if !v.is_array() {
+				return Err(self.error(p, "expected a list of filters"));
+			}
+
+			v.members()
+				.enumerate()
+				.map(|(i, f)| self.filter(f, &format!("{p}[{i}]"), streamtype))
+				.collect::<Result<Vec<_>>>()
+		})?;
+
+		// Attachments are copied as they are
+		if streamtype == Some(StreamType::Attachment)
+			&& filters.as_ref().is_some_and(|f| !f.is_empty())
+		{
+			let reason = "attachments can't be filtered";
+			return Err(self.error(&resolve::child(path, "filters"), reason));
+		}
+
+		Ok(Selector {
+			streamtype,
+			lang: self.optional(data, path, "lang", |v, p| self.string(v, p))?,
+			id: self.optional(data, path, "id", |v, p| self.strings(v, p))?,
+			file: self.optional(data, path, "file", |v, p| self.strings(v, p))?,
+			missing: missing.unwrap_or(false),
+			filters: filters.unwrap_or_default(),
+		})
+	}
+
+	fn filter(
+		&self,
+		data: &JsonValue,
+		path: &str,
+		streamtype: Option<StreamType>,
+	) -> Result<Filter> {
+		if !data.is_object() {
+			return Err(self.error(path, "expected an object"));
+		}
+
+		let name = self.required(data, path, "$type", |v, p| self.string(v, p))?;
+
+		let filter = match name.as_str() {
+			"encode" => {
+				let mut options = Vec::<(String, String)>::new();
+
+				for (key, value) in data.entries() {
+					if key == "$type" || key == "codec" {
+						continue;
+					}
+
+					if value.is_object() || value.is_array() || value.is_null() {
+						let reason = "expected a string or a number";
+						return Err(self.error(&resolve::child(path, key), reason));
+					}
+
+					options.push((String::from(key), value.to_string()));
+				}
+
+				Filter::Encode {
+					codec: self.required(data, path, "codec", |v, p| self.string(v, p))?,
+					options,
+				}
+			}
+			"offset" => {
+				let mut offsets = Vec::<(String, Timestamp)>::new();
+
+				for (key, value) in data.entries() {
+					if key == "$type" {
+						continue;
+					}
+
+					let seconds = match value.as_str() {
+						Some(value) => Timestamp::parse(value),
+						None => value.as_f64().map(Timestamp::from_seconds),
+					};
+
+					if seconds.is_none() {
+						let reason = "expected an offset in seconds";
+						return Err(self.error(&resolve::child(path, key), reason));
+					}
+
+					offsets.push((String::from(key), seconds.unwrap()));
+				}
+
+				Filter::Offset { offsets }
+			}
+			"vapoursynth" | "avisynth" => {
+				self.keys(data, path, &["$type", "filter"])?;
+
+				let filter = self.required(data, path, "filter", |v, p| self.string(v, p))?;
+				let filter = PathBuf::from(filter);
+
+				if name == "vapoursynth" {
+					Filter::VapourSynth { filter }
+				} else {
+					Filter::AviSynth { filter }
+				}
+			}
+			"speed" => {
+				self.keys(data, path, &["$type", "framerate", "input", "output"])?;
+
+				let framerate =
+					self.optional(data, path, "framerate", |v, p| self.framerate(v, p))?;
+				let input = self.optional(data, path, "input", |v, p| self.framerate(v, p))?;
+				let output = self.optional(data, path, "output", |v, p| self.framerate(v, p))?;
+
+				let video = framerate.is_some();
+				let other = input.is_some() && output.is_some();
+
+				// Without a stream type, the matched stream decides which one is needed
+				match streamtype {
+					Some(StreamType::Video) if !video => {
+						return Err(self.error(path, "missing framerate"));
+					}
+					Some(t) if t != StreamType::Video && !other => {
+						return Err(self.error(path, "missing input or output framerate"));
+					}
+					None if !video && !other => {
+						let reason = "missing framerate, or input and output framerate";
+						return Err(self.error(path, reason));
+					}
+					_ => {}
+				}
+
+				Filter::Speed {
+					framerate,
+					input,
+					output,
+				}
+			}
+			"tempo" | "pitch" => {
+				self.keys(data, path, &["$type", "input", "output"])?;
+
+				let input = self.required(data, path, "input", |v, p| self.framerate(v, p))?;
+				let output = self.required(data, path, "output", |v, p| self.framerate(v, p))?;
+
+				if name == "tempo" {
+					Filter::Tempo { input, output }
+				} else {
+					Filter::Pitch { input, output }
+				}
+			}
+			_ => {
+				let reason = format!("unknown filter type {name}");
+				return Err(self.error(&resolve::child(path, "$type"), reason));
+			}
+		};
+
+		Ok(filter)
+	}
+
+	fn pattern(&self, text: String, path: &str) -> Result<Pattern> {
+		let (regex, specificity) = match text.strip_prefix("re:") {
+			// Regexes are too hard to compare, any glob is more specific
+			Some(regex) => (
+				Regex::new(&format!("^(?:{regex})$")).map_err(|e| e.to_string()),
+				0,
+			),
+			None => {
+				let literal = text.chars().filter(|c| !"*?[]".contains(*c)).count();
+				let regex = fnmatch_regex::glob_to_regex(&text).map_err(|e| e.to_string());
+
+				(regex, literal + 1)
+			}
+		};
+
+		let regex = regex.map_err(|e| self.error(path, format!("invalid pattern: {e}")))?;
+
+		Ok(Pattern { regex, specificity })
+	}
+
+	// "parallel", "concat", or {"concat": [globs]} to order the parts
+	fn join(&self, data: &JsonValue, path: &str) -> Result<Join> {
+		match data.as_str() {
+			Some("parallel") => return Ok(Join::Parallel),
+			Some("concat") => return Ok(Join::Concat(Vec::new())),
+			_ => {}
+		}
+
+		if !data.is_object() {
+			let reason = "expected parallel, concat or an object with the order of the parts";
+			return Err(self.error(path, reason));
+		}
+
+		self.keys(data, path, &["concat"])?;
+
+		let globs = self.required(data, path, "concat", |v, p| {
+			self.strings(v, p)?
+				.iter()
+				.enumerate()
+				.map(|(i, glob)| {
+					fnmatch_regex::glob_to_regex(glob)
+						.map_err(|e| self.error(&format!("{p}[{i}]"), format!("invalid glob: {e}")))
+				})
+				.collect::<Result<Vec<_>>>()
+		})?;
+
+		Ok(Join::Concat(globs))
+	}
+
+	// A template, or an object with the template and the pattern for the item
+	fn output(&self, data: &JsonValue, path: &str) -> Result<Output> {
+		let (template, pattern) = if data.is_object() {
+			self.keys(data, path, &["path", "pattern"])?;
+
+			let template = self.required(data, path, "path", |v, p| self.string(v, p))?;
+			let pattern = self.optional(data, path, "pattern", |v, p| {
+				let pattern = self.string(v, p)?;
+				Regex::new(&pattern).map_err(|e| self.error(p, format!("invalid pattern: {e}")))
+			})?;
+
+			(template, pattern)
+		} else {
+			(self.string(data, path)?, None)
+		};
+
+		// Most names of episodes contain S01E02 or similar
+		let pattern = pattern
+			.unwrap_or_else(|| Regex::new(r"(?i)s(?P<season>\d+)\s*e(?P<episode>\d+)").unwrap());
+
+		let output = Output { template, pattern };
+		naming::check(&output).map_err(|e| self.error(path, e))?;
+
+		Ok(output)
+	}
+
+	// "flat", "files", "directories", or a glob pattern like "Season */*" or {"pattern": "*/*"}
+	fn layout(&self, data: &JsonValue, path: &str) -> Result<Layout> {
+		if data.is_object() {
+			self.keys(data, path, &["pattern"])?;
+
+			return self.required(data, path, "pattern", |v, p| {
+				self.pattern_layout(&self.string(v, p)?, p)
+			});
+		}
+
+		let layout = self.string(data, path)?;
+
+		match layout.as_str() {
+			"flat" => Ok(Layout::Flat),
+			"files" => Ok(Layout::Files),
+			"directories" => Ok(Layout::Directories),
+			pattern if pattern.contains(['*', '?', '[']) => self.pattern_layout(pattern, path),
+			other => {
+				let reason = format!(
+					"unknown layout {other}, expected flat, files, directories or a glob pattern"
+				);
+				Err(self.error(path, reason))
+			}
+		}
+	}
+
+	fn pattern_layout(&self, layout: &str, path: &str) -> Result<Layout> {
+		let mut globs = Vec::<Regex>::new();
+
+		for glob in layout.split('/') {
+			if glob.is_empty() {
+				return Err(self.error(path, format!("empty folder level in {layout}")));
+			}
+
+			let regex = fnmatch_regex::glob_to_regex(glob)
+				.map_err(|e| self.error(path, format!("invalid glob {glob}: {e}")))?;
+
+			globs.push(regex);
+		}
+
+		Ok(Layout::Pattern(globs))
+	}
+
+	// "longest", the position of the title, or a duration range like {"min": "20:00"}
+	fn title(&self, data: &JsonValue, path: &str) -> Result<Title> {
+		if data.as_str() == Some("longest") {
+			return Ok(Title::Longest);
+		}
+
+		if let Some(i) = data.as_usize() {
+			if i == 0 {
+				return Err(self.error(path, "titles are numbered from 1"));
+			}
+
+			return Ok(Title::Index(i));
+		}
+
+		if !data.is_object() {
+			let reason = "expected longest, a title number or a duration range";
+			return Err(self.error(path, reason));
+		}
+
+		self.keys(data, path, &["min", "max"])?;
+
+		Ok(Title::Duration {
+			min: self.optional(data, path, "min", |v, p| self.duration(v, p))?,
+			max: self.optional(data, path, "max", |v, p| self.duration(v, p))?,
+		})
+	}
+
+	// Seconds, or a duration like 1:30:00 or 45:00
+	fn duration(&self, value: &JsonValue, path: &str) -> Result<Timestamp> {
+		// Numbers are parsed as they are written, so they stay exact
+		let duration = match value.as_str() {
+			Some(value) => Timestamp::parse(value),
+			None if value.is_number() => Timestamp::parse(&value.dump()),
+			None => None,
+		};
+
+		duration
+			.filter(|d| *d >= Timestamp::ZERO)
+			.ok_or_else(|| self.error(path, "expected seconds or a duration like 1:30:00"))
+	}
+
+	// Rejects keys that are not part of the schema, e.g. typos
+	fn keys(&self, data: &JsonValue, path: &str, allowed: &[&str]) -> Result<()> {
+		if !data.is_object() {
+			return Err(self.error(path, "expected an object"));
+		}
+
+		for (key, _) in data.entries() {
+			if !allowed.contains(&key) {
+				return Err(self.error(&resolve::child(path, key), "unknown key"));
+			}
+		}
+
+		Ok(())
+	}
+
+	fn optional<T, F>(&self, data: &JsonValue, path: &str, key: &str, func: F) -> Result<Option<T>>
+	where
+		F: FnOnce(&JsonValue, &str) -> Result<T>,
+	{
+		if !data.has_key(key) {
+			return Ok(None);
+		}
+
+		func(&data[key], &resolve::child(path, key)).map(Some)
+	}
+
+	fn required<T, F>(&self, data: &JsonValue, path: &str, key: &str, func: F) -> Result<T>
+	where
+		F: FnOnce(&JsonValue, &str) -> Result<T>,
+	{
+		match self.optional(data, path, key, func)? {
+			Some(value) => Ok(value),
+			None => Err(self.error(path, format!("missing {key}"))),
+		}
+	}
+
+	fn string(&self, value: &JsonValue, path: &str) -> Result<String> {
+		match value.as_str() {
+			Some(value) => Ok(String::from(value)),
+			None => Err(self.error(path, "expected a string")),
+		}
+	}
+
+	fn strings(&self, value: &JsonValue, path: &str) -> Result<Vec<String>> {
+		if !value.is_array() {
+			return Err(self.error(path, "expected a list of strings"));
+		}
+
+		value
+			.members()
+			.enumerate()
+			.map(|(i, v)| self.string(v, &format!("{path}[{i}]")))
+			.collect()
+	}
+
+	fn framerate(&self, value: &JsonValue, path: &str) -> Result<(u32, u32)> {
+		let framerate = utils::framerate(&self.string(value, path)?);
+
+		if framerate.0 == 0 || framerate.1 == 0 {
+			return Err(self.error(path, "expected a framerate like 24000/1001 or 25"));
+		}
+
+		Ok(framerate)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use json::object;
+
+	use super::*;
+
+	fn layout(value: JsonValue) -> Result<Layout> {
+		parse(Path::new("test.json"), "test", &object! { layout: value }).map(|c| c.layout)
+	}
+
+	fn error(value: JsonValue) -> String {
+		layout(value).unwrap_err().to_string()
+	}
+
+	fn pattern(value: JsonValue) -> Vec<Regex> {
+		match layout(value).unwrap() {
+			Layout::Pattern(globs) => globs,
+			other => panic!("expected a pattern, got {other:?}"),
+		}
+	}
+
+	#[test]
+	fn layouts() {
+		assert!(matches!(layout("flat".into()).unwrap(), Layout::Flat));
+		assert!(matches!(layout("files".into()).unwrap(), Layout::Files));
+		assert!(matches!(
+			layout("directories".into()).unwrap(),
+			Layout::Directories
+		));
+
+		let globs = pattern("Season */*".into());
+		assert_eq!(globs.len(), 2);
+		assert!(globs[0].is_match("Season 1") && !globs[0].is_match("Extras"));
+		assert!(globs[1].is_match("ep01.mkv"));
+
+		// Folder names without glob characters are only patterns when they are marked as one
+		let globs = pattern(object! { pattern: "Extras" });
+		assert!(globs[0].is_match("Extras") && !globs[0].is_match("Extras 2"));
+
+		let unknown = "layout: unknown layout Season, expected flat, files, directories or a glob";
+		assert!(error("Season".into()).contains(unknown));
+		assert!(error("*//*".into()).contains("layout: empty folder level in *//*"));
+		assert!(error(object! { glob: "*" }).contains("layout.glob: unknown key"));
+		assert!(error(object! {}).contains("layout: missing pattern"));
+		assert!(error(object! { pattern: 1 }).contains("layout.pattern: expected a string"));
+	}
+}
@@ -0,0 +1,438 @@
+use std::path::{Path, PathBuf};
+
+use json::{object, JsonValue};
+
+use crate::error::{Error, Result};
+
+// Operators that change the list of the same key in the linked files or the overridden config
+//
+// Elements to remove or patch are given by index, or by the $type of filters.
+const OPERATORS: &[&str] = &["$append", "$prepend", "$replace", "$remove", "$patch"];
+
+// Per-item changes, kept as they are until they are applied to the configuration of an item
+const OVERRIDES: &str = "overrides";
+
+// Loads a configuration and merges everything it links to into it
+pub fn file(config: &Path, path: &Path) -> Result<JsonValue> {
+	let mut resolver = Resolver {
+		config,
+		chain: Vec::new(),
+	};
+
+	resolver.file(path)
+}
+
+struct Resolver<'a> {
+	config: &'a Path,
+
+	// Files that are currently being resolved, from the configuration to the innermost link
+	chain: Vec<PathBuf>,
+}
+
+impl Resolver<'_> {
+	fn current(&self) -> &Path {
+		self.chain.last().unwrap()
+	}
+
+	fn error(&self, path: &str, reason: impl std::fmt::Display) -> Error {
+		Error::config(self.current(), path, reason)
+	}
+
+	fn file(&mut self, path: &Path) -> Result<JsonValue> {
+		let canonical = path.canonicalize().map_err(|e| Error::io(path, e))?;
+
+		if self.chain.contains(&canonical) {
+			let chain = [self.chain.as_slice(), &[canonical]]
+				.concat()
+				.iter()
+				.map(|p| self.name(p))
+				.collect::<Vec<_>>();
+
+			let reason = format!("link cycle {}", chain.join(" -> "));
+			return Err(self.error("", reason));
+		}
+
+		let data = super::load_dict(path)?;
+
+		self.chain.push(canonical);
+		let data = self.dict(&data, "");
+		self.chain.pop();
+
+		// Operators that had nothing to apply to in this file can't apply to anything later
+		let mut data = data?;
+		for (key, value) in data.entries_mut() {
+			if key != OVERRIDES {
+				*value = finish(value.take());
+			}
+		}
+
+		Ok(data)
+	}
+
+	// Links are shown relative to the config directory, like they are written
+	fn name(&self, path: &Path) -> String {
+		let config = self.config.canonicalize().unwrap_or_default();
+		let name = path.strip_prefix(&config).unwrap_or(path);
+
+		String::from(name.to_str().unwrap())
+	}
+
+	fn dict(&mut self, value: &JsonValue, path: &str) -> Result<JsonValue> {
+		let mut new = object! {};
+
+		// Linked files are merged first, so local keys override them wherever they are written
+		if value.has_key("$link") {
+			let path = child(path, "$link");
+
+			for link in links(&value["$link"]) {
+				let link =
+					link.ok_or_else(|| self.error(&path, "expected a path or a list of paths"))?;
+				let target = self.target(link, &path)?;
+
+				new = merge(new, self.file(&target)?);
+			}
+		}
+
+		for (key, value) in value.entries() {
+			if key == "$link" {
+				continue;
+			}
+
+			let path = child(path, key);
+
+			let value = if key == "$patch" {
+				if !value.is_object() {
+					return Err(self.error(&path, "expected an object of indices or filter types"));
+				}
+
+				self.dict(value, &path)?
+			} else if key == "$remove" {
+				if !value.is_array() || value.members().any(|v| !v.is_number() && !v.is_string()) {
+					return Err(self.error(&path, "expected a list of indices or filter types"));
+				}
+
+				value.clone()
+			} else if OPERATORS.contains(&key) {
+				if !value.is_array() {
+					return Err(self.error(&path, "expected a list"));
+				}
+
+				self.list(value, &path)?
+			} else if value.is_object() {
+				self.dict(value, &path)?
+			} else if value.is_array() {
+				self.list(value, &path)?
+			} else {
+				self.value(value)
+			};
+
+			let old = new[key].take();
+			new[key] = merge(old, value);
+		}
+
+		let operators = new.entries().filter(|(k, _)| OPERATORS.contains(k)).count();
+		if operators > 0 && operators < new.len() {
+			let reason = format!(
+				"{} cannot be combined with other keys",
+				OPERATORS.join(", ")
+			);
+			return Err(self.error(path, reason));
+		}
+
+		Ok(new)
+	}
+
+	fn list(&mut self, value: &JsonValue, path: &str) -> Result<JsonValue> {
+		let mut new = Vec::<JsonValue>::new();
+
+		for (i, entry) in value.members().enumerate() {
+			let path = format!("{path}[{i}]");
+
+			if entry.is_object() {
+				new.push(self.dict(entry, &path)?);
+			} else if entry.is_array() {
+				new.push(self.list(entry, &path)?);
+			} else {
+				new.push(entry.clone());
+			}
+		}
+
+		Ok(JsonValue::Array(new))
+	}
+
+	fn value(&self, value: &JsonValue) -> JsonValue {
+		let s = value.as_str().unwrap_or_default();
+
+		// If a value references the config directory, replace the path and make it absolute
+		if !s.contains("$(configs)$") {
+			return value.clone();
+		}
+
+		let s = s.replace("$(configs)$", self.config.to_str().unwrap());
+
+		let path = PathBuf::from(s);
+		let path = match path.canonicalize() {
+			Ok(val) => val,
+			Err(_) => path,
+		};
+
+		JsonValue::from(path.to_str().unwrap())
+	}
+
+	fn target(&self, link: &str, path: &str) -> Result<PathBuf> {
+		let target = self.config.join(link);
+
+		// The extension can be left out, as long as only one format exists
+		let found = match target.extension() {
+			Some(_) if target.is_file() => Some(target),
+			_ => super::find(&target).map_err(|e| self.error(path, e.cause()))?,
+		};
+
+		found.ok_or_else(|| self.error(path, format!("link target {link} does not exist")))
+	}
+}
+
+fn links(value: &JsonValue) -> Vec<Option<&str>> {
+	if value.is_array() {
+		value.members().map(|v| v.as_str()).collect()
+	} else {
+		vec![value.as_str()]
+	}
+}
+
+// The path of a key, the top level has an empty path
+pub(super) fn child(path: &str, key: &str) -> String {
+	if path.is_empty() {
+		String::from(key)
+	} else {
+		format!("{path}.{key}")
+	}
+}
+
+// Objects are merged recursively, everything else is replaced unless an operator says otherwise
+pub fn merge(old: JsonValue, new: JsonValue) -> JsonValue {
+	if is_operator(&new) {
+		// Without anything to apply to yet, e.g. in a file without links, the operators are kept
+		if old.is_null() {
+			return new;
+		}
+
+		// Multiple operators are applied in the order they are written
+		return new
+			.entries()
+			.fold(finish(old), |old, (op, arg)| apply(old, op, arg));
+	}
+
+	if !old.is_object() || !new.is_object() {
+		return new;
+	}
+
+	let mut old = old;
+	let mut new = new;
+
+	for (key, value) in new.entries_mut() {
+		let prev = old[key].take();
+		old[key] = merge(prev, value.take());
+	}
+
+	old
+}
+
+fn apply(old: JsonValue, op: &str, arg: &JsonValue) -> JsonValue {
+	let old = match old {
+		JsonValue::Array(items) => items,
+		_ => Vec::new(),
+	};
+
+	let items = match op {
+		"$append" => old.into_iter().chain(arg.members().cloned()).collect(),
+		"$prepend" => arg.members().cloned().chain(old).collect(),
+		"$remove" => old
+			.into_iter()
+			.enumerate()
+			.filter(|(i, v)| !arg.members().any(|a| selects(a, *i, v)))
+			.map(|(_, v)| v)
+			.collect(),
+		"$patch" => old
+			.into_iter()
+			.enumerate()
+			.map(|(i, v)| {
+				let patches = arg
+					.entries()
+					.filter(|(k, _)| selects(&JsonValue::from(*k), i, &v))
+					.map(|(_, patch)| patch.clone())
+					.collect::<Vec<_>>();
+
+				patches.into_iter().fold(v, merge)
+			})
+			.collect(),
+		_ => arg.members().cloned().collect(),
+	};
+
+	JsonValue::Array(items)
+}
+
+// Applies the operators that are left to an empty list
+pub fn finish(value: JsonValue) -> JsonValue {
+	if is_operator(&value) {
+		return finish(merge(JsonValue::new_array(), value));
+	}
+
+	match value {
+		JsonValue::Object(_) => {
+			let mut value = value;
+
+			for (_, v) in value.entries_mut() {
+				*v = finish(v.take());
+			}
+
+			value
+		}
+		JsonValue::Array(items) => JsonValue::Array(items.into_iter().map(finish).collect()),
+		_ => value,
+	}
+}
+
+// Whether an element of $remove or a key of $patch refers to the element at index
+fn selects(selector: &JsonValue, index: usize, value: &JsonValue) -> bool {
+	let i = selector
+		.as_usize()
+		.or_else(|| selector.as_str().and_then(|s| s.parse::<usize>().ok()));
+
+	match i {
+		Some(i) => i == index,
+		None => selector.as_str().is_some_and(|s| value["$type"] == s),
+	}
+}
+
+fn is_operator(value: &JsonValue) -> bool {
+	value.is_object() && !value.is_empty() && value.entries().all(|(k, _)| OPERATORS.contains(&k))
+}
+
+#[cfg(test)]
+mod tests {
+	use json::array;
+	use tempdir::TempDir;
+
+	use super::*;
+
+	fn parse(data: &str) -> JsonValue {
+		json::parse(data).unwrap()
+	}
+
+	fn configs(files: &[(&str, &str)]) -> TempDir {
+		let dir = TempDir::new("videoproc").unwrap();
+
+		for (name, data) in files {
+			std::fs::write(dir.path().join(name), data).unwrap();
+		}
+
+		dir
+	}
+
+	fn error(dir: &TempDir, name: &str) -> String {
+		file(dir.path(), &dir.path().join(name))
+			.unwrap_err()
+			.to_string()
+	}
+
+	#[test]
+	fn merges() {
+		let old = parse(r#"{"a": {"b": 1, "c": [1, 2]}, "d": "x"}"#);
+		let new = parse(r#"{"a": {"c": [3]}, "e": true}"#);
+
+		let merged = merge(old, new);
+		assert_eq!(
+			merged,
+			parse(r#"{"a": {"b": 1, "c": [3]}, "d": "x", "e": true}"#)
+		);
+
+		// Anything but an object replaces the old value
+		assert_eq!(merge(parse(r#"{"a": 1}"#), array![1]), array![1]);
+	}
+
+	#[test]
+	fn operators() {
+		let old = parse(r#"[{"$type": "encode"}, {"$type": "speed"}, {"$type": "offset"}]"#);
+
+		let append = merge(array![1, 2], parse(r#"{"$append": [3]}"#));
+		assert_eq!(append, array![1, 2, 3]);
+
+		let prepend = merge(array![1, 2], parse(r#"{"$prepend": [0]}"#));
+		assert_eq!(prepend, array![0, 1, 2]);
+
+		let replace = merge(array![1, 2], parse(r#"{"$replace": [3]}"#));
+		assert_eq!(replace, array![3]);
+
+		let remove = merge(old.clone(), parse(r#"{"$remove": [0, "offset"]}"#));
+		assert_eq!(remove, parse(r#"[{"$type": "speed"}]"#));
+
+		let patch = parse(r#"{"$patch": {"0": {"crf": 20}, "speed": {"framerate": 25}}}"#);
+		assert_eq!(
+			merge(old, patch),
+			parse(
+				r#"[{"$type": "encode", "crf": 20}, {"$type": "speed", "framerate": 25}, {"$type": "offset"}]"#
+			)
+		);
+
+		// Operators are applied in the order they are written
+		let both = merge(array![1], parse(r#"{"$prepend": [0], "$append": [2]}"#));
+		assert_eq!(both, array![0, 1, 2]);
+	}
+
+	#[test]
+	fn unapplied() {
+		// Operators wait for something to apply to, and apply to an empty list at the end
+		let append = parse(r#"{"$append": [1]}"#);
+		assert_eq!(merge(JsonValue::Null, append.clone()), append);
+		assert_eq!(
+			finish(parse(r#"{"a": {"$append": [1]}}"#)),
+			parse(r#"{"a": [1]}"#)
+		);
+	}
+
+	#[test]
+	fn links() {
+		let dir = configs(&[
+			(
+				"base.json",
+				r#"{"video": [{"type": "video"}], "audio": [1, 2]}"#,
+			),
+			("extra.json", r#"{"title": "longest"}"#),
+			(
+				"show.json",
+				r#"{"$link": ["base", "extra.json"], "audio": {"$append": [3]}, "video": []}"#,
+			),
+		]);
+
+		let data = file(dir.path(), &dir.path().join("show.json")).unwrap();
+
+		assert_eq!(data["video"], array![]);
+		assert_eq!(data["audio"], array![1, 2, 3]);
+		assert_eq!(data["title"], "longest");
+		assert!(!data.has_key("$link"));
+	}
+
+	#[test]
+	fn cycle() {
+		let dir = configs(&[
+			("a.json", r#"{"$link": "b"}"#),
+			("b.json", r#"{"$link": "a"}"#),
+		]);
+
+		assert!(error(&dir, "a.json").contains("link cycle a.json -> b.json -> a.json"));
+	}
+
+	#[test]
+	fn invalid() {
+		let dir = configs(&[
+			("missing.json", r#"{"$link": "other"}"#),
+			("mixed.json", r#"{"a": {"$append": [1], "b": 2}}"#),
+			("remove.json", r#"{"a": {"$remove": [{}]}}"#),
+		]);
+
+		assert!(error(&dir, "missing.json").contains("$link: link target other does not exist"));
+		assert!(error(&dir, "mixed.json").contains("a: $append"));
+		assert!(error(&dir, "remove.json").contains("a.$remove: expected a list"));
+	}
+}
@@ -0,0 +1,512 @@
+use std::path::Path;
+
+use json::JsonValue;
+
+use crate::error::{Error, Result};
+
+use super::resolve;
+
+// Values that differ for every item, only known once the item is processed
+pub struct Builtins<'a> {
+	pub item: &'a str,
+	pub input: &'a Path,
+	pub working: &'a Path,
+}
+
+const BUILTINS: &[&str] = &["item", "input", "working"];
+
+// Replaces $(name)$ and $(expression)$ in all strings, and removes the variables
+//
+// Without builtins, references to them are kept so they can be filled in per item.
+pub fn interpolate(
+	file: &Path,
+	data: &JsonValue,
+	builtins: Option<&Builtins>,
+) -> Result<JsonValue> {
+	let mut interpolator = Interpolator {
+		file,
+		vars: &data["$vars"],
+		builtins,
+		stack: Vec::new(),
+	};
+
+	if !data["$vars"].is_null() && !data["$vars"].is_object() {
+		return Err(Error::config(file, "$vars", "expected an object"));
+	}
+
+	let mut data = data.clone();
+	data.remove("$vars");
+
+	interpolator.value(&data, "")
+}
+
+struct Interpolator<'a> {
+	file: &'a Path,
+	vars: &'a JsonValue,
+	builtins: Option<&'a Builtins<'a>>,
+
+	// Variables that are currently being expanded, to detect variables that reference themselves
+	stack: Vec<String>,
+}
+
+impl Interpolator<'_> {
+	fn error(&self, path: &str, reason: impl std::fmt::Display) -> Error {
+		Error::config(self.file, path, reason)
+	}
+
+	fn value(&mut self, value: &JsonValue, path: &str) -> Result<JsonValue> {
+		let mut new = value.clone();
+
+		if value.is_object() {
+			for (key, value) in value.entries() {
+				new[key] = self.value(value, &resolve::child(path, key))?;
+			}
+		} else if value.is_array() {
+			for (i, value) in value.members().enumerate() {
+				new[i] = self.value(value, &format!("{path}[{i}]"))?;
+			}
+		} else if let Some(s) = value.as_str() {
+			new = self.string(s, path)?;
+		}
+
+		Ok(new)
+	}
+
+	fn string(&mut self, s: &str, path: &str) -> Result<JsonValue> {
+		// A string that is only a variable keeps the type of the variable, e.g. a number
+		if let Some(name) = s.strip_prefix("$(").and_then(|s| s.strip_suffix(")$")) {
+			if identifier(name) {
+				if let Some(value) = self.lookup(name, path)? {
+					return Ok(value);
+				}
+			}
+		}
+
+		let mut result = String::new();
+		let mut rest = s;
+
+		while let Some(start) = rest.find("$(") {
+			let end = rest[start..].find(")$");
+			if end.is_none() {
+				break;
+			}
+
+			let end = start + end.unwrap();
+			let expr = rest[start + 2..end].trim();
+
+			result.push_str(&rest[..start]);
+
+			if identifier(expr) {
+				match self.lookup(expr, path)? {
+					Some(value) if value.is_object() || value.is_array() => {
+						let reason = format!("variable {expr} cannot be part of a string");
+						return Err(self.error(path, reason));
+					}
+					Some(value) => result.push_str(&value.to_string()),
+					None => result.push_str(&rest[start..end + 2]),
+				}
+			} else {
+				let value = Expression {
+					interpolator: self,
+					path,
+					chars: expr.chars().collect(),
+					pos: 0,
+				}
+				.parse()?;
+
+				result.push_str(&format(value));
+			}
+
+			rest = &rest[end + 2..];
+		}
+
+		result.push_str(rest);
+		Ok(JsonValue::from(result))
+	}
+
+	// Returns None for builtins that are filled in later
+	fn lookup(&mut self, name: &str, path: &str) -> Result<Option<JsonValue>> {
+		if BUILTINS.contains(&name) {
+			return Ok(self.builtins.map(|b| {
+				let value = match name {
+					"item" => b.item,
+					"input" => b.input.to_str().unwrap(),
+					_ => b.working.to_str().unwrap(),
+				};
+
+				JsonValue::from(value)
+			}));
+		}
+
+		if let Some(var) = name.strip_prefix("env.") {
+			return match std::env::var(var) {
+				Ok(value) => Ok(Some(JsonValue::from(value))),
+				Err(_) => Err(self.error(path, format!("environment variable {var} is not set"))),
+			};
+		}
+
+		if !self.vars.has_key(name) {
+			return Err(self.error(path, format!("unknown variable {name}")));
+		}
+
+		if self.stack.iter().any(|v| v == name) {
+			let chain = [self.stack.join(" -> "), String::from(name)].join(" -> ");
+			return Err(self.error(path, format!("variable cycle {chain}")));
+		}
+
+		// Variables can use other variables, errors point at the variable itself
+		self.stack.push(String::from(name));
+		let value = self.value(&self.vars[name].clone(), &format!("$vars.{name}"));
+		self.stack.pop();
+
+		value.map(Some)
+	}
+}
+
+fn identifier(s: &str) -> bool {
+	let mut chars = s.chars();
+
+	let first = chars
+		.next()
+		.is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+	first && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+// Rationals are exact, so 24000/1001 * 25/24 is still a valid framerate
+type Rational = (i64, i64);
+
+fn format(value: Rational) -> String {
+	if value.1 == 1 {
+		value.0.to_string()
+	} else {
+		format!("{}/{}", value.0, value.1)
+	}
+}
+
+fn reduce(num: i64, den: i64) -> Option<Rational> {
+	if den == 0 {
+		return None;
+	}
+
+	let (mut a, mut b) = (num.abs(), den.abs());
+	while b != 0 {
+		(a, b) = (b, a % b);
+	}
+
+	let sign = if den < 0 { -1 } else { 1 };
+	Some((sign * num / a.max(1), sign * den / a.max(1)))
+}
+
+// Parses framerates like 24000/1001, 25 or 23.976
+fn rational(s: &str) -> Option<Rational> {
+	if let Some(s) = s.strip_prefix('-') {
+		return rational(s).map(|(num, den)| (-num, den));
+	}
+
+	if let Some((num, den)) = s.split_once('/') {
+		let num = rational(num.trim())?;
+		let den = rational(den.trim())?;
+
+		return reduce(num.0.checked_mul(den.1)?, num.1.checked_mul(den.0)?);
+	}
+
+	match s.split_once('.') {
+		Some((int, frac)) => {
+			if frac.is_empty() || !frac.chars().all(|c| c.is_ascii_digit()) {
+				return None;
+			}
+
+			let den = 10i64.checked_pow(frac.len() as u32)?;
+			let int = if int.is_empty() {
+				0
+			} else {
+				int.parse::<i64>().ok()?
+			};
+
+			reduce(
+				int.checked_mul(den)?
+					.checked_add(frac.parse::<i64>().ok()?)?,
+				den,
+			)
+		}
+		None => Some((s.parse::<i64>().ok()?, 1)),
+	}
+}
+
+// Arithmetic with + - * / and parentheses on numbers, framerates and variables holding them
+struct Expression<'a, 'b> {
+	interpolator: &'a mut Interpolator<'b>,
+	path: &'a str,
+	chars: Vec<char>,
+	pos: usize,
+}
+
+impl Expression<'_, '_> {
+	fn error(&self, reason: impl std::fmt::Display) -> Error {
+		let expr = self.chars.iter().collect::<String>();
+		let reason = format!("invalid expression {expr}: {reason}");
+
+		self.interpolator.error(self.path, reason)
+	}
+
+	fn parse(mut self) -> Result<Rational> {
+		let value = self.sum()?;
+
+		self.skip();
+		if self.pos < self.chars.len() {
+			return Err(self.error(format!("unexpected {}", self.chars[self.pos])));
+		}
+
+		Ok(value)
+	}
+
+	fn skip(&mut self) {
+		while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+			self.pos += 1;
+		}
+	}
+
+	fn next(&mut self, ops: &[char]) -> Option<char> {
+		self.skip();
+
+		let c = self.chars.get(self.pos).copied();
+		if c.is_some_and(|c| ops.contains(&c)) {
+			self.pos += 1;
+			return c;
+		}
+
+		None
+	}
+
+	fn sum(&mut self) -> Result<Rational> {
+		let mut value = self.product()?;
+
+		while let Some(op) = self.next(&['+', '-']) {
+			let rhs = self.product()?;
+
+			let result = if op == '+' {
+				value
+					.0
+					.checked_mul(rhs.1)
+					.zip(rhs.0.checked_mul(value.1))
+					.and_then(|(a, b)| a.checked_add(b))
+			} else {
+				value
+					.0
+					.checked_mul(rhs.1)
+					.zip(rhs.0.checked_mul(value.1))
+					.and_then(|(a, b)| a.checked_sub(b))
+			};
+
+			let den = value.1.checked_mul(rhs.1);
+			value = result
+				.zip(den)
+				.and_then(|(num, den)| reduce(num, den))
+				.ok_or_else(|| self.error("overflow"))?;
+		}
+
+		Ok(value)
+	}
+
+	fn product(&mut self) -> Result<Rational> {
+		let mut value = self.factor()?;
+
+		while let Some(op) = self.next(&['*', '/']) {
+			let rhs = self.factor()?;
+
+			let (num, den) = if op == '*' {
+				(value.0.checked_mul(rhs.0), value.1.checked_mul(rhs.1))
+			} else {
+				(value.0.checked_mul(rhs.1), value.1.checked_mul(rhs.0))
+			};
+
+			if op == '/' && rhs.0 == 0 {
+				return Err(self.error("division by zero"));
+			}
+
+			value = num
+				.zip(den)
+				.and_then(|(num, den)| reduce(num, den))
+				.ok_or_else(|| self.error("overflow"))?;
+		}
+
+		Ok(value)
+	}
+
+	fn factor(&mut self) -> Result<Rational> {
+		if self.next(&['-']).is_some() {
+			let value = self.factor()?;
+			return Ok((-value.0, value.1));
+		}
+
+		if self.next(&['(']).is_some() {
+			let value = self.sum()?;
+
+			if self.next(&[')']).is_none() {
+				return Err(self.error("missing )"));
+			}
+
+			return Ok(value);
+		}
+
+		self.skip();
+		let start = self.pos;
+
+		while self.pos < self.chars.len() {
+			let c = self.chars[self.pos];
+
+			if !(c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+				break;
+			}
+
+			self.pos += 1;
+		}
+
+		let token = self.chars[start..self.pos].iter().collect::<String>();
+		if token.is_empty() {
+			return Err(self.error("expected a number or a variable"));
+		}
+
+		if !identifier(&token) {
+			return rational(&token).ok_or_else(|| self.error(format!("invalid number {token}")));
+		}
+
+		let value = self.interpolator.lookup(&token, self.path)?;
+		let value = value.ok_or_else(|| self.error(format!("{token} is only known per item")))?;
+
+		let s = value
+			.as_str()
+			.map(String::from)
+			.unwrap_or_else(|| value.to_string());
+		rational(&s).ok_or_else(|| self.error(format!("{token} is not a number or framerate")))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use json::object;
+
+	use super::*;
+
+	fn run(data: JsonValue, builtins: Option<&Builtins>) -> Result<JsonValue> {
+		interpolate(Path::new("test.json"), &data, builtins)
+	}
+
+	fn expression(expr: &str) -> Result<JsonValue> {
+		let data = object! {
+			"$vars": { fps: "24000/1001", half: 0.5, zero: 0 },
+			value: format!("$({expr})$"),
+		};
+
+		run(data, None).map(|mut d| d["value"].take())
+	}
+
+	#[test]
+	fn rationals() {
+		assert_eq!(reduce(6, 4), Some((3, 2)));
+		assert_eq!(reduce(6, -4), Some((-3, 2)));
+		assert_eq!(reduce(0, 5), Some((0, 1)));
+		assert_eq!(reduce(1, 0), None);
+
+		assert_eq!(rational("24000/1001"), Some((24000, 1001)));
+		assert_eq!(rational("23.976"), Some((2997, 125)));
+		assert_eq!(rational(".5"), Some((1, 2)));
+		assert_eq!(rational("-25"), Some((-25, 1)));
+		assert_eq!(rational("1/0"), None);
+		assert_eq!(rational("1."), None);
+		assert_eq!(rational("x"), None);
+
+		assert_eq!(format((25, 1)), "25");
+		assert_eq!(format((24000, 1001)), "24000/1001");
+	}
+
+	#[test]
+	fn expressions() {
+		assert_eq!(expression("1 + 2 * 3").unwrap(), "7");
+		assert_eq!(expression("(1 + 2) * 3").unwrap(), "9");
+		assert_eq!(expression("fps * 25/24").unwrap(), "25000/1001");
+		assert_eq!(expression("-half - 1").unwrap(), "-3/2");
+		assert_eq!(expression("1 / 3 + 1 / 6").unwrap(), "1/2");
+
+		let errors = [
+			("1 / zero", "division by zero"),
+			("(1 + 2", "missing )"),
+			("1 +", "expected a number or a variable"),
+			("2 x", "unexpected x"),
+			("1 + missing", "unknown variable missing"),
+			("item * 2", "item is only known per item"),
+			("9223372036854775807 * 2", "overflow"),
+		];
+
+		for (expr, reason) in errors {
+			let err = expression(expr).unwrap_err().to_string();
+			assert!(err.contains(reason), "{expr}: {err}");
+		}
+	}
+
+	#[test]
+	fn variables() {
+		let data = object! {
+			"$vars": { name: "show", crf: 18, list: [1, 2], outer: "$(name)$-$(crf)$" },
+			crf: "$(crf)$",
+			title: "$(outer)$",
+			list: "$(list)$",
+		};
+
+		let data = run(data, None).unwrap();
+
+		// A string that is only a variable keeps its type
+		assert_eq!(data["crf"], 18);
+		assert_eq!(data["title"], "show-18");
+		assert_eq!(data["list"], json::array![1, 2]);
+		assert!(!data.has_key("$vars"));
+
+		let errors = [
+			(object! { a: "$(b)$" }, "a: unknown variable b"),
+			(
+				object! { "$vars": { a: "$(b)$", b: "$(a)$" }, c: "$(a)$" },
+				"variable cycle a -> b -> a",
+			),
+			(
+				object! { "$vars": { a: [1] }, b: "x $(a)$" },
+				"variable a cannot be part of a string",
+			),
+			(object! { "$vars": 1 }, "$vars: expected an object"),
+		];
+
+		for (data, reason) in errors {
+			let err = run(data, None).unwrap_err().to_string();
+			assert!(err.contains(reason), "{err}");
+		}
+	}
+
+	#[test]
+	fn environment() {
+		std::env::set_var("VIDEOPROC_TEST_CRF", "20");
+
+		let data = object! { crf: "$(env.VIDEOPROC_TEST_CRF)$" };
+		assert_eq!(run(data, None).unwrap()["crf"], "20");
+
+		let data = object! { crf: "$(env.VIDEOPROC_TEST_UNSET)$" };
+		let err = run(data, None).unwrap_err().to_string();
+		assert!(err.contains("environment variable VIDEOPROC_TEST_UNSET is not set"));
+	}
+
+	#[test]
+	fn builtins() {
+		let data = object! { path: "$(input)$/$(item)$.txt", working: "$(working)$" };
+
+		// Without an item, builtins are kept for later
+		assert_eq!(run(data.clone(), None).unwrap(), data);
+
+		let builtins = Builtins {
+			item: "Season 1/ep01",
+			input: Path::new("/input/show"),
+			working: Path::new("/working/show/ep01"),
+		};
+
+		let data = run(data, Some(&builtins)).unwrap();
+		assert_eq!(data["path"], "/input/show/Season 1/ep01.txt");
+		assert_eq!(data["working"], "/working/show/ep01");
+	}
+}
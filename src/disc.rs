@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+	config::Title,
+	error::{Error, Result},
+	logging,
+	timestamp::Timestamp,
+	tools,
+};
+
+// A playlist of a Blu-ray or a title set of a DVD
+pub struct Entry {
+	pub name: String,
+
+	// The playlist, or the first VOB of the title set
+	pub path: PathBuf,
+	pub duration: Timestamp,
+}
+
+// Disc backups are directories that contain the BDMV or VIDEO_TS folder of the disc
+pub fn is_disc(path: &Path) -> bool {
+	path.join("BDMV").is_dir() || path.join("VIDEO_TS").is_dir()
+}
+
+// Whether a file is the title of a disc, which is remuxed differently than other inputs
+pub fn is_title(file: &Path) -> bool {
+	let ext = file.extension().unwrap_or_default().to_ascii_lowercase();
+	let parent = file
+		.parent()
+		.and_then(|p| p.file_name())
+		.unwrap_or_default();
+
+	ext == "mpls" || ext == "vob" && parent == "VIDEO_TS"
+}
+
+pub fn titles(path: &Path) -> Result<Vec<Entry>> {
+	if path.join("BDMV").is_dir() {
+		playlists(&path.join("BDMV").join("PLAYLIST"))
+	} else {
+		titlesets(&path.join("VIDEO_TS"))
+	}
+}
+
+// Picks the title of a disc the configuration asks for
+pub fn title(path: &Path, title: &Title) -> Result<PathBuf> {
+	let titles = titles(path)?;
+
+	for (i, entry) in titles.iter().enumerate() {
+		logging::info!(
+			"Title {}: {} ({})",
+			i + 1,
+			entry.name,
+			duration(entry.duration)
+		);
+	}
+
+	let entry = match title {
+		Title::Longest => longest(titles.iter()),
+		Title::Index(i) => titles.get(i.wrapping_sub(1)),
+		Title::Duration { min, max } => longest(
+			titles
+				.iter()
+				.filter(|t| min.is_none_or(|m| t.duration >= m))
+				.filter(|t| max.is_none_or(|m| t.duration <= m)),
+		),
+	};
+
+	let entry = entry.ok_or_else(|| {
+		let reason = format!("none of the {} titles matches {}", titles.len(), title);
+		Error::parse(path, reason)
+	})?;
+
+	logging::info!("Using title {} ({})", entry.name, duration(entry.duration));
+	Ok(entry.path.clone())
+}
+
+// The files that make up a title, VOBs of a title set are split into 1 GB parts
+pub fn parts(file: &Path) -> Vec<PathBuf> {
+	let name = file
+		.file_name()
+		.unwrap_or_default()
+		.to_str()
+		.unwrap_or_default();
+
+	if !is_title(file) || !name.ends_with("_1.VOB") {
+		return vec![file.to_path_buf()];
+	}
+
+	let prefix = &name[..name.len() - "1.VOB".len()];
+	let mut parts = Vec::<PathBuf>::new();
+
+	for i in 1.. {
+		let part = file.with_file_name(format!("{prefix}{i}.VOB"));
+		if !part.is_file() {
+			break;
+		}
+
+		parts.push(part);
+	}
+
+	parts
+}
+
+// A file ffprobe can read before the title was remuxed, e.g. for a dry run
+pub fn preview(file: &Path) -> Result<PathBuf> {
+	if !file
+		.extension()
+		.unwrap_or_default()
+		.eq_ignore_ascii_case("mpls")
+	{
+		return Ok(file.to_path_buf());
+	}
+
+	let data = identify(file)?;
+	let clip = data["container"]["properties"]["playlist_file"][0].as_str();
+
+	clip.map(PathBuf::from)
+		.ok_or_else(|| Error::parse(file, "playlist without clips"))
+}
+
+fn playlists(dir: &Path) -> Result<Vec<Entry>> {
+	let mut titles = Vec::<Entry>::new();
+
+	for path in files(dir)? {
+		if !path
+			.extension()
+			.unwrap_or_default()
+			.eq_ignore_ascii_case("mpls")
+		{
+			continue;
+		}
+
+		let data = identify(&path)?;
+		// mkvmerge gives the duration of playlists in nanoseconds
+		let duration = data["container"]["properties"]["playlist_duration"]
+			.as_i64()
+			.unwrap_or_default();
+
+		titles.push(Entry {
+			name: path.file_stem().unwrap().to_str().unwrap().to_string(),
+			path,
+			duration: Timestamp::from_nanos(duration),
+		});
+	}
+
+	Ok(titles)
+}
+
+fn titlesets(dir: &Path) -> Result<Vec<Entry>> {
+	let mut titles = Vec::<Entry>::new();
+
+	// VTS_01_0.VOB is the menu of the title set, the title itself starts at VTS_01_1.VOB
+	for path in files(dir)? {
+		let name = path.file_name().unwrap().to_str().unwrap();
+		if !name.starts_with("VTS_") || !name.ends_with("_1.VOB") {
+			continue;
+		}
+
+		let mut duration = Timestamp::ZERO;
+
+		for part in parts(&path) {
+			let data = tools::output(
+				tools::FFPROBE
+					.command()
+					.arg(&part)
+					.arg("-of")
+					.arg("json")
+					.arg("-show_format"),
+			)?;
+
+			let data = json::parse(&data).map_err(|e| Error::parse(&part, e))?;
+			let length = data["format"]["duration"].as_str().unwrap_or_default();
+
+			duration += Timestamp::parse(length).unwrap_or_default();
+		}
+
+		titles.push(Entry {
+			name: name[..name.len() - "_1.VOB".len()].to_string(),
+			path,
+			duration,
+		});
+	}
+
+	Ok(titles)
+}
+
+fn identify(file: &Path) -> Result<json::JsonValue> {
+	let data = tools::output(tools::MKVMERGE.command().arg("-J").arg(file))?;
+	json::parse(&data).map_err(|e| Error::parse(file, e))
+}
+
+fn longest<'a>(entries: impl Iterator<Item = &'a Entry>) -> Option<&'a Entry> {
+	entries.max_by_key(|e| e.duration)
+}
+
+fn files(dir: &Path) -> Result<Vec<PathBuf>> {
+	let entries = std::fs::read_dir(dir).map_err(|e| Error::io(dir, e))?;
+	let mut files = entries
+		.filter_map(|e| e.ok().map(|e| e.path()))
+		.filter(|p| p.is_file())
+		.collect::<Vec<_>>();
+
+	files.sort();
+	Ok(files)
+}
+
+fn duration(duration: Timestamp) -> String {
+	let seconds = duration.seconds();
+	format!(
+		"{}:{:02}:{:02}",
+		seconds / 3600,
+		seconds / 60 % 60,
+		seconds % 60
+	)
+}
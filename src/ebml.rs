@@ -0,0 +1,786 @@
+use std::{
+	collections::BTreeMap,
+	fs::File,
+	io::{BufReader, Read, Seek, SeekFrom},
+	path::{Path, PathBuf},
+};
+
+use crate::error::{Error, Result};
+
+// Element IDs of the parts of Matroska we read, everything else is skipped
+const EBML: u32 = 0x1A45DFA3;
+const DOCTYPE: u32 = 0x4282;
+const SEGMENT: u32 = 0x18538067;
+
+const SEEKHEAD: u32 = 0x114D9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEKID: u32 = 0x53AB;
+const SEEKPOSITION: u32 = 0x53AC;
+
+const INFO: u32 = 0x1549A966;
+const TIMESTAMPSCALE: u32 = 0x2AD7B1;
+
+const TRACKS: u32 = 0x1654AE6B;
+const TRACKENTRY: u32 = 0xAE;
+const TRACKNUMBER: u32 = 0xD7;
+const TRACKUID: u32 = 0x73C5;
+const TRACKTYPE: u32 = 0x83;
+const CODECID: u32 = 0x86;
+const LANGUAGE: u32 = 0x22B59C;
+const DEFAULTDURATION: u32 = 0x23E383;
+const VIDEO: u32 = 0xE0;
+const PIXELWIDTH: u32 = 0xB0;
+const PIXELHEIGHT: u32 = 0xBA;
+const DISPLAYWIDTH: u32 = 0x54B0;
+const DISPLAYHEIGHT: u32 = 0x54BA;
+const FLAGINTERLACED: u32 = 0x9A;
+const AUDIO: u32 = 0xE1;
+const SAMPLINGFREQUENCY: u32 = 0xB5;
+const OUTPUTSAMPLINGFREQUENCY: u32 = 0x78B5;
+const CHANNELS: u32 = 0x9F;
+const BITDEPTH: u32 = 0x6264;
+
+const TAGS: u32 = 0x1254C367;
+const TAG: u32 = 0x7373;
+const TARGETS: u32 = 0x63C0;
+const TAGTRACKUID: u32 = 0x63C5;
+const SIMPLETAG: u32 = 0x67C8;
+const TAGNAME: u32 = 0x45A3;
+const TAGSTRING: u32 = 0x4487;
+
+const CHAPTERS: u32 = 0x1043A770;
+const EDITIONENTRY: u32 = 0x45B9;
+const CHAPTERATOM: u32 = 0xB6;
+const CHAPTERTIMESTART: u32 = 0x91;
+
+const ATTACHMENTS: u32 = 0x1941A469;
+const ATTACHEDFILE: u32 = 0x61A7;
+const FILENAME: u32 = 0x466E;
+const FILEMIMETYPE: u32 = 0x4660;
+
+const CLUSTER: u32 = 0x1F43B675;
+const TIMESTAMP: u32 = 0xE7;
+const SIMPLEBLOCK: u32 = 0xA3;
+const BLOCKGROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+
+// The first blocks of every track are in the first few clusters
+const CLUSTERS: usize = 8;
+
+// Strings and numbers are small, anything larger is a broken file
+const LIMIT: u64 = 1 << 20;
+
+#[derive(Default)]
+pub struct Matroska {
+	// Nanoseconds per timestamp tick
+	pub scale: u64,
+	pub tracks: Vec<Track>,
+	pub chapters: usize,
+	pub attachments: Vec<Attachment>,
+}
+
+#[derive(Default)]
+pub struct Track {
+	pub number: u64,
+	pub uid: u64,
+
+	// 1 is video, 2 audio and 17 subtitles
+	pub kind: u64,
+	pub codec: String,
+	pub language: Option<String>,
+
+	// Nanoseconds per frame
+	pub frame: Option<u64>,
+
+	pub width: Option<u64>,
+	pub height: Option<u64>,
+	pub display: Option<(u64, u64)>,
+	pub progressive: bool,
+
+	pub samplerate: Option<f64>,
+	pub channels: Option<u64>,
+	pub bitdepth: Option<u64>,
+
+	// Tags that target this track, like DURATION or SOURCE_ID
+	pub tags: BTreeMap<String, String>,
+
+	// Timestamp of the first block in nanoseconds, if it is in the first clusters
+	pub start: Option<i64>,
+}
+
+pub struct Attachment {
+	pub name: String,
+	pub mime: String,
+}
+
+pub fn is_matroska(path: &Path) -> bool {
+	let mut magic = [0; 4];
+	let read = File::open(path).and_then(|mut f| f.read_exact(&mut magic));
+
+	read.is_ok() && u32::from_be_bytes(magic) == EBML
+}
+
+pub fn read(path: &Path) -> Result<Matroska> {
+	let file = File::open(path).map_err(|e| Error::io(path, e))?;
+	let length = file.metadata().map_err(|e| Error::io(path, e))?.len();
+
+	let mut reader = Reader {
+		file: BufReader::new(file),
+		path: path.to_path_buf(),
+		length,
+	};
+
+	reader.document()
+}
+
+struct Reader<R> {
+	file: R,
+	path: PathBuf,
+	length: u64,
+}
+
+// Tags are collected by track UID, the tracks may only come after them
+type Tags = BTreeMap<u64, BTreeMap<String, String>>;
+
+impl<R: Read + Seek> Reader<R> {
+	fn error(&self, reason: impl std::fmt::Display) -> Error {
+		Error::parse(&self.path, reason)
+	}
+
+	fn io(&self, err: std::io::Error) -> Error {
+		Error::io(&self.path, err)
+	}
+
+	fn pos(&mut self) -> Result<u64> {
+		self.file.stream_position().map_err(|e| self.io(e))
+	}
+
+	fn seek(&mut self, pos: u64) -> Result<()> {
+		self.file
+			.seek(SeekFrom::Start(pos))
+			.map(|_| ())
+			.map_err(|e| self.io(e))
+	}
+
+	fn byte(&mut self) -> Result<u8> {
+		let mut byte = [0];
+		self.file.read_exact(&mut byte).map_err(|e| self.io(e))?;
+
+		Ok(byte[0])
+	}
+
+	// Variable length integers, the leading zeros of the first byte tell the length
+	fn vint(&mut self, marker: bool) -> Result<(u64, u32)> {
+		let first = self.byte()?;
+		let length = first.leading_zeros() + 1;
+
+		if length > 8 {
+			return Err(self.error("invalid variable length integer"));
+		}
+
+		let mut value = if marker {
+			first as u64
+		} else {
+			(first as u64) & (0xFF >> length)
+		};
+
+		for _ in 1..length {
+			value = (value << 8) | self.byte()? as u64;
+		}
+
+		Ok((value, length))
+	}
+
+	// The ID and size of the next element, None at the end of the file
+	fn header(&mut self) -> Result<Option<(u32, Option<u64>)>> {
+		if self.pos()? >= self.length {
+			return Ok(None);
+		}
+
+		let (id, _) = self.vint(true)?;
+		let (size, length) = self.vint(false)?;
+
+		// All bits set means the size is unknown, e.g. for live streams
+		let unknown = size == (1 << (7 * length)) - 1;
+
+		Ok(Some((id as u32, if unknown { None } else { Some(size) })))
+	}
+
+	fn bytes(&mut self, size: u64) -> Result<Vec<u8>> {
+		if size > LIMIT {
+			return Err(self.error(format!("element of {size} bytes is too large")));
+		}
+
+		let mut data = vec![0; size as usize];
+		self.file.read_exact(&mut data).map_err(|e| self.io(e))?;
+
+		Ok(data)
+	}
+
+	fn uint(&mut self, size: u64) -> Result<u64> {
+		if size > 8 {
+			return Err(self.error("integer of more than 8 bytes"));
+		}
+
+		Ok(self
+			.bytes(size)?
+			.iter()
+			.fold(0, |value, b| (value << 8) | *b as u64))
+	}
+
+	fn int(&mut self, size: u64) -> Result<i64> {
+		let value = self.uint(size)?;
+
+		// Sign extend from the size of the element
+		let shift = 64 - 8 * size.max(1) as u32;
+		Ok(((value << shift) as i64) >> shift)
+	}
+
+	fn float(&mut self, size: u64) -> Result<f64> {
+		let data = self.bytes(size)?;
+
+		match size {
+			0 => Ok(0.0),
+			4 => Ok(f32::from_be_bytes(data.try_into().unwrap()) as f64),
+			8 => Ok(f64::from_be_bytes(data.try_into().unwrap())),
+			_ => Err(self.error("float of invalid size")),
+		}
+	}
+
+	fn string(&mut self, size: u64) -> Result<String> {
+		let data = self.bytes(size)?;
+		let data = String::from_utf8_lossy(&data);
+
+		Ok(String::from(data.trim_end_matches('\0')))
+	}
+
+	// Calls func for every child of a master element, skipping what it does not read
+	fn children<F>(&mut self, size: Option<u64>, mut func: F) -> Result<()>
+	where
+		F: FnMut(&mut Self, u32, u64) -> Result<()>,
+	{
+		let size = size.ok_or_else(|| self.error("element of unknown size"))?;
+		let end = self.pos()? + size;
+
+		while self.pos()? < end {
+			let (id, size) = match self.header()? {
+				Some(header) => header,
+				None => break,
+			};
+
+			let size = size.ok_or_else(|| self.error("element of unknown size"))?;
+			let next = self.pos()? + size;
+
+			func(self, id, size)?;
+			self.seek(next)?;
+		}
+
+		self.seek(end)
+	}
+
+	fn document(&mut self) -> Result<Matroska> {
+		let (id, size) = self.header()?.ok_or_else(|| self.error("empty file"))?;
+
+		if id != EBML {
+			return Err(self.error("not a Matroska file"));
+		}
+
+		let mut doctype = String::new();
+		self.children(size, |r, id, size| {
+			if id == DOCTYPE {
+				doctype = r.string(size)?;
+			}
+
+			Ok(())
+		})?;
+
+		if doctype != "matroska" && doctype != "webm" {
+			return Err(self.error(format!("unknown document type {doctype}")));
+		}
+
+		loop {
+			match self.header()? {
+				Some((SEGMENT, size)) => return self.segment(size),
+				Some((_, Some(size))) => {
+					let pos = self.pos()?;
+					self.seek(pos + size)?;
+				}
+				_ => return Err(self.error("missing segment")),
+			}
+		}
+	}
+
+	fn segment(&mut self, size: Option<u64>) -> Result<Matroska> {
+		let start = self.pos()?;
+		let end = size.map_or(self.length, |s| (start + s).min(self.length));
+
+		let mut mkv = Matroska {
+			scale: 1_000_000,
+			..Default::default()
+		};
+
+		let mut tags = Tags::new();
+		let mut seeks = Vec::<(u32, u64)>::new();
+		let mut read = Vec::<u32>::new();
+		let mut clusters = 0;
+
+		while self.pos()? < end {
+			let (id, size) = match self.header()? {
+				Some(header) => header,
+				None => break,
+			};
+
+			let pos = self.pos()?;
+
+			if id == CLUSTER {
+				if clusters < CLUSTERS {
+					clusters += 1;
+					self.cluster(size, &mut mkv)?;
+				}
+
+				// Without a size, only the seek head can tell where the rest is
+				match size {
+					Some(size) if clusters < CLUSTERS || seeks.is_empty() => {
+						self.seek(pos + size)?;
+						continue;
+					}
+					_ => break,
+				}
+			}
+
+			self.element(id, size, &mut mkv, &mut tags, &mut seeks)?;
+			read.push(id);
+
+			let size = size.ok_or_else(|| self.error("element of unknown size"))?;
+			self.seek(pos + size)?;
+		}
+
+		// Tags and other elements written after the clusters are found through the seek heads
+		let mut i = 0;
+		while i < seeks.len() {
+			let (id, pos) = seeks[i];
+			i += 1;
+
+			if read.contains(&id) || id == CLUSTER || start + pos >= end {
+				continue;
+			}
+
+			self.seek(start + pos)?;
+
+			match self.header()? {
+				Some((found, size)) if found == id => {
+					self.element(id, size, &mut mkv, &mut tags, &mut seeks)?;
+					read.push(id);
+				}
+				_ => return Err(self.error("seek head points to the wrong element")),
+			}
+		}
+
+		for track in &mut mkv.tracks {
+			if let Some(tags) = tags.remove(&track.uid) {
+				track.tags = tags;
+			}
+		}
+
+		Ok(mkv)
+	}
+
+	fn element(
+		&mut self,
+		id: u32,
+		size: Option<u64>,
+		mkv: &mut Matroska,
+		tags: &mut Tags,
+		seeks: &mut Vec<(u32, u64)>,
+	) -> Result<()> {
+		match id {
+			SEEKHEAD => self.children(size, |r, id, size| {
+				if id != SEEK {
+					return Ok(());
+				}
+
+				let mut seek = (0, None);
+				r.children(Some(size), |r, id, size| {
+					match id {
+						SEEKID => seek.0 = r.uint(size)? as u32,
+						SEEKPOSITION => seek.1 = Some(r.uint(size)?),
+						_ => {}
+					}
+
+					Ok(())
+				})?;
+
+				if let (id, Some(pos)) = seek {
+					seeks.push((id, pos));
+				}
+
+				Ok(())
+			}),
+			INFO => self.children(size, |r, id, size| {
+				if id == TIMESTAMPSCALE {
+					mkv.scale = r.uint(size)?;
+				}
+
+				Ok(())
+			}),
+			TRACKS => self.children(size, |r, id, size| {
+				if id == TRACKENTRY {
+					mkv.tracks.push(r.track(size)?);
+				}
+
+				Ok(())
+			}),
+			TAGS => self.children(size, |r, id, size| {
+				if id == TAG {
+					r.tag(size, tags)?;
+				}
+
+				Ok(())
+			}),
+			CHAPTERS => self.children(size, |r, id, size| {
+				if id != EDITIONENTRY {
+					return Ok(());
+				}
+
+				r.children(Some(size), |r, id, size| {
+					if id == CHAPTERATOM {
+						r.children(Some(size), |_, id, _| {
+							if id == CHAPTERTIMESTART {
+								mkv.chapters += 1;
+							}
+
+							Ok(())
+						})?;
+					}
+
+					Ok(())
+				})
+			}),
+			ATTACHMENTS => self.children(size, |r, id, size| {
+				if id != ATTACHEDFILE {
+					return Ok(());
+				}
+
+				let mut attachment = Attachment {
+					name: String::new(),
+					mime: String::new(),
+				};
+
+				r.children(Some(size), |r, id, size| {
+					match id {
+						FILENAME => attachment.name = r.string(size)?,
+						FILEMIMETYPE => attachment.mime = r.string(size)?,
+						_ => {}
+					}
+
+					Ok(())
+				})?;
+
+				mkv.attachments.push(attachment);
+				Ok(())
+			}),
+			_ => Ok(()),
+		}
+	}
+
+	fn track(&mut self, size: u64) -> Result<Track> {
+		// Matroska assumes English for tracks without a language
+		let mut track = Track {
+			language: Some(String::from("eng")),
+			..Default::default()
+		};
+
+		self.children(Some(size), |r, id, size| {
+			match id {
+				TRACKNUMBER => track.number = r.uint(size)?,
+				TRACKUID => track.uid = r.uint(size)?,
+				TRACKTYPE => track.kind = r.uint(size)?,
+				CODECID => track.codec = r.string(size)?,
+				LANGUAGE => track.language = Some(r.string(size)?),
+				DEFAULTDURATION => track.frame = Some(r.uint(size)?),
+				VIDEO => {
+					let mut display = (None, None);
+
+					r.children(Some(size), |r, id, size| {
+						match id {
+							PIXELWIDTH => track.width = Some(r.uint(size)?),
+							PIXELHEIGHT => track.height = Some(r.uint(size)?),
+							DISPLAYWIDTH => display.0 = Some(r.uint(size)?),
+							DISPLAYHEIGHT => display.1 = Some(r.uint(size)?),
+							FLAGINTERLACED => track.progressive = r.uint(size)? == 2,
+							_ => {}
+						}
+
+						Ok(())
+					})?;
+
+					// The display size defaults to the pixel size
+					let width = display.0.or(track.width);
+					let height = display.1.or(track.height);
+					track.display = width.zip(height);
+				}
+				AUDIO => r.children(Some(size), |r, id, size| {
+					match id {
+						SAMPLINGFREQUENCY => {
+							let rate = r.float(size)?;
+							track.samplerate = Some(track.samplerate.unwrap_or(rate));
+						}
+
+						// What the decoder outputs, e.g. for HE-AAC
+						OUTPUTSAMPLINGFREQUENCY => track.samplerate = Some(r.float(size)?),
+						CHANNELS => track.channels = Some(r.uint(size)?),
+						BITDEPTH => track.bitdepth = Some(r.uint(size)?),
+						_ => {}
+					}
+
+					Ok(())
+				})?,
+				_ => {}
+			}
+
+			Ok(())
+		})?;
+
+		// Undetermined is the same as no language at all
+		if track.language.as_deref() == Some("und") {
+			track.language = None;
+		}
+
+		Ok(track)
+	}
+
+	fn tag(&mut self, size: u64, tags: &mut Tags) -> Result<()> {
+		let mut uids = Vec::<u64>::new();
+		let mut values = Vec::<(String, String)>::new();
+
+		self.children(Some(size), |r, id, size| {
+			match id {
+				TARGETS => r.children(Some(size), |r, id, size| {
+					if id == TAGTRACKUID {
+						uids.push(r.uint(size)?);
+					}
+
+					Ok(())
+				})?,
+				SIMPLETAG => {
+					let mut value = (String::new(), String::new());
+
+					r.children(Some(size), |r, id, size| {
+						match id {
+							TAGNAME => value.0 = r.string(size)?,
+							TAGSTRING => value.1 = r.string(size)?,
+							_ => {}
+						}
+
+						Ok(())
+					})?;
+
+					values.push(value);
+				}
+				_ => {}
+			}
+
+			Ok(())
+		})?;
+
+		// Tags without a track apply to the whole file, which we don't need
+		for uid in uids {
+			let entry = tags.entry(uid).or_default();
+			entry.extend(values.iter().cloned());
+		}
+
+		Ok(())
+	}
+
+	// Finds the earliest timestamp of every track in a cluster
+	fn cluster(&mut self, size: Option<u64>, mkv: &mut Matroska) -> Result<()> {
+		let start = self.pos()?;
+		let end = size.map_or(self.length, |s| start + s);
+		let mut timestamp = 0;
+
+		while self.pos()? < end {
+			let (id, size) = match self.header()? {
+				Some(header) => header,
+				None => break,
+			};
+
+			// Clusters of unknown size end where the next top level element starts
+			if [CLUSTER, TAGS, CHAPTERS, ATTACHMENTS, 0x1C53BB6B].contains(&id) {
+				break;
+			}
+
+			let size = size.ok_or_else(|| self.error("element of unknown size"))?;
+			let next = self.pos()? + size;
+
+			match id {
+				TIMESTAMP => timestamp = self.uint(size)? as i64,
+				// Blocks cut off at the end of the file are skipped
+				SIMPLEBLOCK if next <= self.length => self.block(timestamp, mkv)?,
+				BLOCKGROUP => {
+					let end = next;
+
+					while self.pos()? < end {
+						// A file cut off in the middle of a block group has no more blocks
+						let (id, size) = match self.header()? {
+							Some(header) => header,
+							None => break,
+						};
+
+						let size = size.ok_or_else(|| self.error("element of unknown size"))?;
+						let next = self.pos()? + size;
+
+						if id == BLOCK && next <= self.length {
+							self.block(timestamp, mkv)?;
+						}
+
+						self.seek(next)?;
+					}
+				}
+				_ => {}
+			}
+
+			self.seek(next)?;
+		}
+
+		Ok(())
+	}
+
+	fn block(&mut self, cluster: i64, mkv: &mut Matroska) -> Result<()> {
+		let (number, _) = self.vint(false)?;
+		let relative = self.int(2)?;
+
+		let start = (cluster + relative) * mkv.scale as i64;
+
+		if let Some(track) = mkv.tracks.iter_mut().find(|t| t.number == number) {
+			track.start = Some(track.start.map_or(start, |s| s.min(start)));
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+
+	fn reader(data: &[u8]) -> Reader<Cursor<Vec<u8>>> {
+		Reader {
+			file: Cursor::new(data.to_vec()),
+			path: PathBuf::from("test.mkv"),
+			length: data.len() as u64,
+		}
+	}
+
+	// An element with a known size, written with an 8 byte size
+	fn element(id: u32, data: &[u8]) -> Vec<u8> {
+		let mut result = id
+			.to_be_bytes()
+			.iter()
+			.copied()
+			.skip_while(|b| *b == 0)
+			.collect::<Vec<_>>();
+		result.push(0x01);
+		result.extend_from_slice(&(data.len() as u64).to_be_bytes()[1..]);
+		result.extend_from_slice(data);
+		result
+	}
+
+	fn document(segment: &[u8]) -> Vec<u8> {
+		let mut data = element(EBML, &element(DOCTYPE, b"matroska"));
+		data.extend(element(SEGMENT, segment));
+		data
+	}
+
+	fn tracks() -> Vec<u8> {
+		let mut track = element(TRACKNUMBER, &[1]);
+		track.extend(element(TRACKTYPE, &[2]));
+		track.extend(element(CODECID, b"A_AC3"));
+
+		element(TRACKS, &element(TRACKENTRY, &track))
+	}
+
+	#[test]
+	fn vint() {
+		assert_eq!(reader(&[0x81]).vint(false).unwrap(), (1, 1));
+		assert_eq!(reader(&[0x40, 0x02]).vint(false).unwrap(), (2, 2));
+		assert_eq!(
+			reader(&[0x1A, 0x45, 0xDF, 0xA3]).vint(true).unwrap(),
+			(EBML as u64, 4)
+		);
+
+		assert!(reader(&[0x00]).vint(false).is_err());
+		assert!(reader(&[0x40]).vint(false).is_err());
+	}
+
+	#[test]
+	fn header() {
+		let mut r = reader(&[0xE7, 0x82, 0x01, 0x02]);
+		assert_eq!(r.header().unwrap(), Some((TIMESTAMP, Some(2))));
+		assert_eq!(r.uint(2).unwrap(), 0x0102);
+		assert_eq!(r.header().unwrap(), None);
+
+		let mut r = reader(&[
+			0x1F, 0x43, 0xB6, 0x75, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+		]);
+		assert_eq!(r.header().unwrap(), Some((CLUSTER, None)));
+	}
+
+	#[test]
+	fn values() {
+		assert_eq!(reader(b"eng\0\0").string(5).unwrap(), "eng");
+		assert_eq!(reader(&[0xFF, 0xFE]).int(2).unwrap(), -2);
+		assert_eq!(reader(&[]).uint(0).unwrap(), 0);
+
+		let rate = 48000f32.to_be_bytes();
+		assert_eq!(reader(&rate).float(4).unwrap(), 48000.0);
+
+		let rate = 44100f64.to_be_bytes();
+		assert_eq!(reader(&rate).float(8).unwrap(), 44100.0);
+
+		assert!(reader(&[0; 3]).float(3).is_err());
+		assert!(reader(&[0; 9]).uint(9).is_err());
+	}
+
+	#[test]
+	fn tracks_and_start() {
+		let mut block = vec![0x81, 0x00, 0x2A, 0x80];
+		block.extend_from_slice(b"data");
+
+		let mut cluster = element(TIMESTAMP, &[100]);
+		cluster.extend(element(SIMPLEBLOCK, &block));
+
+		let mut segment = tracks();
+		segment.extend(element(CLUSTER, &cluster));
+
+		let mkv = reader(&document(&segment)).document().unwrap();
+
+		assert_eq!(mkv.tracks.len(), 1);
+		assert_eq!(mkv.tracks[0].codec, "A_AC3");
+		assert_eq!(mkv.tracks[0].language.as_deref(), Some("eng"));
+		assert_eq!(mkv.tracks[0].start, Some(142_000_000));
+	}
+
+	#[test]
+	fn truncated() {
+		let block = element(BLOCK, &[0x81, 0x00, 0x00, 0x80]);
+
+		let mut cluster = element(TIMESTAMP, &[0]);
+		cluster.extend(element(BLOCKGROUP, &[block.clone(), block].concat()));
+
+		let mut segment = tracks();
+		segment.extend(element(CLUSTER, &cluster));
+
+		let data = document(&segment);
+
+		// Cut off inside the block group, the blocks before are still read
+		let cut = &data[..data.len() - 2];
+		let mkv = reader(cut).document().unwrap();
+		assert_eq!(mkv.tracks[0].start, Some(0));
+
+		// Cut off inside the header or the tracks
+		assert!(reader(&data[..6]).document().is_err());
+		assert!(reader(&[]).document().is_err());
+	}
+}
@@ -0,0 +1,217 @@
+use std::{
+	any::Any,
+	fmt, io,
+	path::{Path, PathBuf},
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+	// A tool could not be started
+	Spawn {
+		command: String,
+		source: io::Error,
+	},
+
+	// A tool exited with an unexpected exit code, log has its full output
+	Exit {
+		command: String,
+		code: Option<i32>,
+		stderr: String,
+		log: Option<PathBuf>,
+	},
+
+	// The output of a tool or a file written by us could not be parsed
+	Parse {
+		path: PathBuf,
+		reason: String,
+	},
+
+	// A configuration is invalid, path points to the offending JSON value
+	Config {
+		file: PathBuf,
+		path: String,
+		reason: String,
+	},
+
+	// A filter is missing options it needs, or they are invalid
+	Filter {
+		filter: String,
+		reason: String,
+	},
+
+	Io {
+		path: PathBuf,
+		source: io::Error,
+	},
+
+	// A stream entry of the configuration did not match any input stream
+	Select {
+		stream: String,
+	},
+
+	// Multiple configurations match an input folder and none of them takes precedence
+	Ambiguous {
+		dir: String,
+		configs: Vec<String>,
+	},
+
+	// Another item or file already has the name the output template gives an item
+	Collision {
+		path: PathBuf,
+		owner: Option<String>,
+	},
+
+	// A thread processing an item or stream panicked
+	Panic {
+		message: String,
+	},
+
+	// Adds information about what was being done when the error happened
+	Context {
+		context: String,
+		source: Box<Error>,
+	},
+}
+
+impl Error {
+	pub fn io(path: &Path, source: io::Error) -> Self {
+		Self::Io {
+			path: path.to_path_buf(),
+			source,
+		}
+	}
+
+	pub fn parse(path: &Path, reason: impl fmt::Display) -> Self {
+		Self::Parse {
+			path: path.to_path_buf(),
+			reason: reason.to_string(),
+		}
+	}
+
+	pub fn config(file: &Path, path: &str, reason: impl fmt::Display) -> Self {
+		Self::Config {
+			file: file.to_path_buf(),
+			path: String::from(path),
+			reason: reason.to_string(),
+		}
+	}
+
+	pub fn filter(filter: &str, reason: impl fmt::Display) -> Self {
+		Self::Filter {
+			filter: String::from(filter),
+			reason: reason.to_string(),
+		}
+	}
+
+	pub fn panic(payload: Box<dyn Any + Send>) -> Self {
+		let message = match payload.downcast::<String>() {
+			Ok(message) => *message,
+			Err(payload) => match payload.downcast::<&str>() {
+				Ok(message) => String::from(*message),
+				Err(_) => String::from("unknown cause"),
+			},
+		};
+
+		Self::Panic { message }
+	}
+
+	// The error without any context
+	pub fn cause(&self) -> &Error {
+		match self {
+			Self::Context { source, .. } => source.cause(),
+			_ => self,
+		}
+	}
+
+	pub fn context(self, context: impl fmt::Display) -> Self {
+		Self::Context {
+			context: context.to_string(),
+			source: Box::new(self),
+		}
+	}
+
+	// All contexts from the outermost to the innermost, e.g. the stream and stage that failed
+	pub fn contexts(&self) -> Vec<&str> {
+		match self {
+			Self::Context { context, source } => {
+				[vec![context.as_str()], source.contexts()].concat()
+			}
+			_ => Vec::new(),
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Spawn { command, source } => {
+				write!(f, "Failed to run {command}: {source}")
+			}
+			Self::Exit {
+				command,
+				code,
+				stderr,
+				log,
+			} => {
+				match code {
+					Some(code) => write!(f, "{command} exited with code {code}")?,
+					None => write!(f, "{command} was terminated by a signal")?,
+				}
+
+				if !stderr.is_empty() {
+					write!(f, "\n{stderr}")?;
+				}
+
+				if let Some(log) = log {
+					write!(f, "\nFull output in {}", log.to_str().unwrap())?;
+				}
+
+				Ok(())
+			}
+			Self::Parse { path, reason } => {
+				write!(f, "Failed to parse {}: {}", path.to_str().unwrap(), reason)
+			}
+			Self::Config { file, path, reason } => {
+				let file = file.to_str().unwrap();
+
+				if path.is_empty() {
+					write!(f, "{file}: {reason}")
+				} else {
+					write!(f, "{file}: {path}: {reason}")
+				}
+			}
+			Self::Filter { filter, reason } => {
+				write!(f, "Invalid {filter} filter: {reason}")
+			}
+			Self::Io { path, source } => {
+				write!(f, "{}: {}", path.to_str().unwrap(), source)
+			}
+			Self::Select { stream } => {
+				write!(f, "Could not find match for stream {stream}")
+			}
+			Self::Ambiguous { dir, configs } => {
+				let configs = configs.join(", ");
+				write!(
+					f,
+					"Input directory {dir} is matched equally well by {configs}"
+				)
+			}
+			Self::Collision { path, owner } => {
+				let path = path.to_str().unwrap();
+
+				match owner {
+					Some(owner) => write!(f, "Output {path} is already written by {owner}"),
+					None => write!(f, "Output {path} exists and was not written by this item"),
+				}
+			}
+			Self::Panic { message } => {
+				write!(f, "Internal error: {message}")
+			}
+			Self::Context { context, source } => {
+				write!(f, "{context}: {source}")
+			}
+		}
+	}
+}
@@ -1,18 +1,34 @@
-use std::{
-	path::Path,
-	process::{Command, Stdio},
+use std::{io, path::Path, process::Stdio};
+
+use crate::{
+	codec::Codec,
+	error::{Error, Result},
+	logging, mkv,
+	tools::{self, Tool},
 };
 
-use execute::Execute;
+// Placeholders in templates that are replaced before the script runs
+pub const PLACEHOLDERS: &[&str] = &["mkv", "avs", "mpg", "d2v", "pass"];
+
+pub fn tools(filter: &Path) -> Vec<Tool> {
+	let mut tools = vec![tools::AVS2YUV, tools::FFMPEG];
 
-use crate::{logging, mkv, utils};
+	let template = std::fs::read_to_string(filter).unwrap_or_default();
 
-pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::Stream, ()> {
-	if !filter.exists() {
-		logging::error!("Filter not found!");
-		return Err(());
+	if template.contains("$(d2v)$") || template.contains("$(mpg)$") {
+		tools.push(tools::MKVEXTRACT);
 	}
 
+	if template.contains("$(d2v)$") {
+		tools.push(tools::DGINDEX);
+	}
+
+	tools
+}
+
+pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::Stream> {
+	let template = std::fs::read_to_string(filter).map_err(|e| Error::io(filter, e))?;
+
 	let name = filter.file_stem().unwrap().to_str().unwrap();
 	logging::info!("Filtering stream using AviSynth filter {}", name);
 
@@ -22,28 +38,19 @@ pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::St
 	let mpg = script.with_extension("avs.mpg");
 	let d2v = script.with_extension("avs.d2v");
 
-	let template = std::fs::read_to_string(filter);
-	if let Err(err) = template {
-		logging::error!("Failed to read filter: {}", err);
-		return Err(());
-	}
-
-	let mut template = template.unwrap();
+	let mut template = template;
 	template = template.replace("$(mkv)$", stream.path.to_str().unwrap());
 	template = template.replace("$(avs)$", filter.to_str().unwrap());
 
 	// If requested extract the MPEG-2 stream
 	if template.contains("$(d2v)$") || template.contains("$(mpg)$") {
-		let cmd = Command::new("mkvextract")
-			.arg(stream.path.to_str().unwrap())
-			.arg("tracks")
-			.arg(format!("0:{}", &mpg.to_str().unwrap()))
-			.execute_check_exit_status_code(0);
-
-		if let Err(err) = cmd {
-			logging::error!("Failed to extract video stream: {}", err);
-			return Err(());
-		}
+		tools::run(
+			tools::MKVEXTRACT
+				.command()
+				.arg(stream.path.to_str().unwrap())
+				.arg("tracks")
+				.arg(format!("0:{}", &mpg.to_str().unwrap())),
+		)?;
 
 		template = template.replace("$(mpg)$", mpg.to_str().unwrap());
 	}
@@ -54,20 +61,17 @@ pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::St
 		let project = d2v.with_extension("");
 		let project_name = project.file_name().unwrap();
 
-		let cmd = Command::new("DGIndex")
-			.current_dir(mpg.parent().unwrap())
-			.arg("-i")
-			.arg(mpg_name.to_str().unwrap())
-			.arg("-o")
-			.arg(project_name.to_str().unwrap())
-			.arg("-exit")
-			.arg("-hide")
-			.execute_check_exit_status_code(0);
-
-		if let Err(err) = cmd {
-			logging::error!("Failed to create D2V index: {}", err);
-			return Err(());
-		}
+		tools::run(
+			tools::DGINDEX
+				.command()
+				.current_dir(mpg.parent().unwrap())
+				.arg("-i")
+				.arg(mpg_name.to_str().unwrap())
+				.arg("-o")
+				.arg(project_name.to_str().unwrap())
+				.arg("-exit")
+				.arg("-hide"),
+		)?;
 
 		template = template.replace("$(d2v)$", d2v.to_str().unwrap());
 	}
@@ -77,54 +81,36 @@ pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::St
 		let script = script.with_extension("pass1.avs");
 		let p1 = template.replace("$(pass)$", "1");
 
-		let err = std::fs::write(&script, p1);
-		if let Err(err) = err {
-			logging::error!("Failed to write avisynth script: {}", err);
-			return Err(());
-		}
+		std::fs::write(&script, p1).map_err(|e| Error::io(&script, e))?;
 
-		let avspipe = Command::new("avs2yuv")
-			.current_dir(script.parent().unwrap())
-			.arg(script.to_str().unwrap())
-			.arg("-o")
-			.arg("-")
-			.execute_check_exit_status_code(0);
+		let mut pass = tools::spawn(
+			tools::AVS2YUV
+				.command()
+				.current_dir(script.parent().unwrap())
+				.arg(script.to_str().unwrap())
+				.arg("-o")
+				.arg("-"),
+		)?;
 
-		if let Err(err) = avspipe {
-			logging::error!("Failed to run avs2yuv: {}", err);
-			return Err(());
-		}
+		// The first pass only collects statistics, its video is not needed
+		let _ = io::copy(&mut pass.stdout().unwrap(), &mut io::sink());
+		pass.wait(&[0])?;
 
-		let err = std::fs::remove_file(script);
-		if let Err(err) = err {
-			logging::error!("Failed to remove file: {}", err);
-			return Err(());
-		}
+		std::fs::remove_file(&script).map_err(|e| Error::io(&script, e))?;
 
 		template = template.replace("$(pass)$", "2");
 	}
 
-	let err = std::fs::write(&script, template);
-	if let Err(err) = err {
-		logging::error!("Failed to write avisynth script: {}", err);
-		return Err(());
-	}
+	std::fs::write(&script, template).map_err(|e| Error::io(&script, e))?;
 
-	let avspipe = Command::new("avs2yuv")
-		.current_dir(script.parent().unwrap())
-		.arg(script.to_str().unwrap())
-		.arg("-o")
-		.arg("-")
-		.stdout(Stdio::piped())
-		.stderr(Stdio::null())
-		.spawn();
-
-	if let Err(err) = avspipe {
-		logging::error!("Failed to run avs2yuv: {}", err);
-		return Err(());
-	}
-
-	let mut avspipe = avspipe.unwrap();
+	let mut avspipe = tools::spawn(
+		tools::AVS2YUV
+			.command()
+			.current_dir(script.parent().unwrap())
+			.arg(script.to_str().unwrap())
+			.arg("-o")
+			.arg("-"),
+	)?;
 
 	let mut args = vec!["-i", "pipe:", "-codec", "ffv1", "-map", "0"];
 
@@ -136,55 +122,39 @@ pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::St
 	args.push("-y");
 	args.push(path.to_str().unwrap());
 
-	let ffmpeg = Command::new("ffmpeg")
-		.current_dir(script.parent().unwrap())
-		.args(args)
-		.stdin(Stdio::from(avspipe.stdout.take().unwrap()))
-		.output();
+	let ffmpeg = tools::run(
+		tools::FFMPEG
+			.command()
+			.current_dir(script.parent().unwrap())
+			.args(args)
+			.stdin(Stdio::from(avspipe.stdout().unwrap())),
+	);
 
-	let ffmpeg = utils::check_output(ffmpeg);
 	if let Err(err) = ffmpeg {
-		let _ = avspipe.kill();
-		logging::error!("Failed to run ffmpeg: {}", err);
-		return Err(());
+		avspipe.kill();
+		let _ = avspipe.wait(&[0]);
+		return Err(err);
 	}
 
-	let probe = mkv::stream(&path, 0);
+	avspipe.wait(&[0])?;
 
-	if probe.is_err() {
-		logging::error!("Failed to probe output file!");
-		return Err(());
-	}
-
-	let probe = probe.unwrap();
-	let speedup = stream.duration / probe.duration;
+	let probe = mkv::stream(&path, 0)?;
+	let speedup = stream.duration.ratio(probe.duration);
 
-	let err = std::fs::remove_file(script);
-	if let Err(err) = err {
-		logging::error!("Failed to remove file: {}", err);
-		return Err(());
-	}
+	std::fs::remove_file(&script).map_err(|e| Error::io(&script, e))?;
 
 	if mpg.exists() {
-		let err = std::fs::remove_file(mpg);
-		if let Err(err) = err {
-			logging::error!("Failed to remove file: {}", err);
-			return Err(());
-		}
+		std::fs::remove_file(&mpg).map_err(|e| Error::io(&mpg, e))?;
 	}
 
 	if d2v.exists() {
-		let err = std::fs::remove_file(d2v);
-		if let Err(err) = err {
-			logging::error!("Failed to remove file: {}", err);
-			return Err(());
-		}
+		std::fs::remove_file(&d2v).map_err(|e| Error::io(&d2v, e))?;
 	}
 
 	let mut new = stream.clone();
 	new.path = path;
 	new.index = 0;
-	new.codec = Some(String::from("ffv1"));
+	new.codec = Some(Codec::Ffv1);
 	new.offset /= speedup;
 	new.duration /= speedup;
 
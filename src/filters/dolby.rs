@@ -1,9 +1,16 @@
-use execute::Execute;
-use std::{path::Path, process::Command};
+use std::path::Path;
 
-use crate::{logging, mkv, utils::StrVec};
+use crate::{
+	codec::Codec,
+	error::Result,
+	logging, mkv,
+	tools::{self, Tool},
+	utils::StrVec,
+};
 
-pub fn normalize(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream, ()> {
+pub const TOOLS: &[Tool] = &[tools::FFMPEG];
+
+pub fn normalize(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream> {
 	logging::info!("Normalizing AC3 audio");
 
 	let path = output.join(&stream.id).with_extension("norm.w64");
@@ -13,9 +20,9 @@ pub fn normalize(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream, ()>
 	args.push_str("-drc_scale");
 	args.push_str("0");
 
-	if stream.dialnorm.is_some() {
+	if let Some(dialnorm) = stream.dialnorm {
 		args.push_str("-target_level");
-		args.push(format!("{}", stream.dialnorm.unwrap()));
+		args.push(format!("{}", dialnorm));
 	}
 
 	args.push_str("-i");
@@ -27,19 +34,12 @@ pub fn normalize(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream, ()>
 	args.push_str("-y");
 	args.push_str(path.to_str().unwrap());
 
-	let cmd = Command::new("ffmpeg")
-		.args(args)
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run ffmpeg: {}", err);
-		return Err(());
-	}
+	tools::run(tools::FFMPEG.command().args(args))?;
 
 	let mut new = stream.clone();
 	new.path = path.clone();
 	new.index = 0;
-	new.codec = Some(String::from("pcm_f32le"));
+	new.codec = Some(Codec::PcmF32le);
 
 	Ok(new)
 }
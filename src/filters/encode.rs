@@ -1,18 +1,24 @@
-use std::{path::Path, process::Command};
+use std::path::Path;
 
-use execute::Execute;
-use json::JsonValue;
 use tempdir::TempDir;
 
-use crate::{logging, mkv, utils::StrVec};
+use crate::{
+	codec::Codec,
+	error::{Error, Result},
+	logging,
+	mkv::{self, StreamType},
+	tools::{self, Tool},
+	utils::StrVec,
+};
 
-pub fn run(stream: &mkv::Stream, output: &Path, options: &JsonValue) -> Result<mkv::Stream, ()> {
-	if !options.has_key("codec") {
-		logging::error!("Did not find codec!");
-		return Err(());
-	}
+pub const TOOLS: &[Tool] = &[tools::FFMPEG];
 
-	let codec = options["codec"].to_string();
+pub fn run(
+	stream: &mkv::Stream,
+	output: &Path,
+	codec: &str,
+	options: &[(String, String)],
+) -> Result<mkv::Stream> {
 	let path = output.join(&stream.id).with_extension("enc.mkv");
 
 	logging::info!("Encoding stream with codec {}", codec);
@@ -26,9 +32,9 @@ pub fn run(stream: &mkv::Stream, output: &Path, options: &JsonValue) -> Result<m
 	args.push_str("-map");
 	args.push(map);
 
-	if stream.aspect.is_some() {
+	if let Some(aspect) = &stream.aspect {
 		args.push_str("-aspect");
-		args.push(stream.aspect.as_ref().unwrap().clone());
+		args.push(aspect.clone());
 	}
 
 	if codec == "ac3" {
@@ -40,19 +46,14 @@ pub fn run(stream: &mkv::Stream, output: &Path, options: &JsonValue) -> Result<m
 	}
 
 	// Is this a two-pass encode?
-	if stream.streamtype == "video" && options.has_key("bitrate") {
-		let temp = TempDir::new("videoproc");
-		if let Err(err) = temp {
-			logging::error!("Failed to create directory: {}", err);
-			return Err(());
-		}
-
-		let temp = temp.unwrap();
+	if stream.streamtype == StreamType::Video && options.iter().any(|(k, _)| k == "bitrate") {
+		let temp = TempDir::new("videoproc").map_err(|e| Error::io(&std::env::temp_dir(), e))?;
 		let log = temp.path().join("ffmpeg2pass");
 
 		let mut p1 = args.clone();
 		let mut cargs = options_to_args(
 			stream,
+			codec,
 			options,
 			Some(1),
 			Some(format!("{}-{}", log.to_str().unwrap(), stream.index)),
@@ -70,18 +71,12 @@ pub fn run(stream: &mkv::Stream, output: &Path, options: &JsonValue) -> Result<m
 		p1.push_str("null");
 		p1.push_str("-");
 
-		let cmd = Command::new("ffmpeg")
-			.args(p1)
-			.execute_check_exit_status_code(0);
-
-		if let Err(err) = cmd {
-			logging::error!("Failed to run ffmpeg: {}", err);
-			return Err(());
-		}
+		tools::run(tools::FFMPEG.command().args(p1))?;
 
 		let mut p2 = args.clone();
 		let mut cargs = options_to_args(
 			stream,
+			codec,
 			options,
 			Some(2),
 			Some(format!("{}-{}", log.to_str().unwrap(), stream.index)),
@@ -98,29 +93,15 @@ pub fn run(stream: &mkv::Stream, output: &Path, options: &JsonValue) -> Result<m
 		p2.push_str("-y");
 		p2.push_str(path.to_str().unwrap());
 
-		let cmd = Command::new("ffmpeg")
-			.args(p2)
-			.execute_check_exit_status_code(0);
-
-		if let Err(err) = cmd {
-			logging::error!("Failed to run ffmpeg: {}", err);
-			return Err(());
-		}
+		tools::run(tools::FFMPEG.command().args(p2))?;
 	} else {
-		let mut cargs = options_to_args(stream, options, None, None);
+		let mut cargs = options_to_args(stream, codec, options, None, None);
 		args.append(&mut cargs);
 
 		args.push_str("-y");
 		args.push_str(path.to_str().unwrap());
 
-		let cmd = Command::new("ffmpeg")
-			.args(args)
-			.execute_check_exit_status_code(0);
-
-		if let Err(err) = cmd {
-			logging::error!("Failed to run ffmpeg: {}", err);
-			return Err(());
-		}
+		tools::run(tools::FFMPEG.command().args(args))?;
 	}
 
 	let mut new = stream.clone();
@@ -128,7 +109,7 @@ pub fn run(stream: &mkv::Stream, output: &Path, options: &JsonValue) -> Result<m
 	new.index = 0;
 
 	if codec != "copy" {
-		new.codec = Some(codec);
+		new.codec = Some(Codec::parse(codec));
 	}
 
 	Ok(new)
@@ -136,22 +117,28 @@ pub fn run(stream: &mkv::Stream, output: &Path, options: &JsonValue) -> Result<m
 
 fn options_to_args(
 	stream: &mkv::Stream,
-	options: &JsonValue,
+	codec: &str,
+	options: &[(String, String)],
 	pass: Option<u32>,
 	passlog: Option<String>,
 ) -> Vec<String> {
 	let mut args = Vec::<String>::new();
 
-	for entry in options.entries() {
-		let mut key = entry.0;
-		let mut val = entry.1.to_string();
+	args.push_str("-codec");
+	args.push_str(codec);
+
+	for entry in options {
+		let mut key = entry.0.as_str();
+		let mut val = entry.1.clone();
 
 		if key == "bitrate" {
-			if stream.streamtype == "video" {
-				key = "b:v"
-			} else if stream.streamtype == "audio" {
-				key = "b:a";
-				val = format!("{}*{}", val, stream.channels.unwrap());
+			match stream.streamtype {
+				StreamType::Video => key = "b:v",
+				StreamType::Audio => {
+					key = "b:a";
+					val = format!("{}*{}", val, stream.channels.unwrap());
+				}
+				_ => {}
 			}
 		}
 
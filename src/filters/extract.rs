@@ -1,101 +1,66 @@
-use std::{path::Path, process::Command};
+use std::path::Path;
 
-use execute::Execute;
+use crate::{
+	codec::Codec,
+	error::{Error, Result},
+	logging,
+	mkv::{self, StreamType},
+	tools::{self, Tool},
+};
 
-use crate::{logging, mkv, utils};
+pub const TOOLS: &[Tool] = &[tools::MKVEXTRACT, tools::MKVMERGE, tools::FFMPEG];
 
-pub fn run(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream, ()> {
+pub fn run(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream> {
 	logging::info!("Extracting stream");
 
-	if stream.streamtype != "chapters" {
-		extract_stream(stream, output)
-	} else {
-		extract_chapters(stream, output)
+	match stream.streamtype {
+		StreamType::Video | StreamType::Audio | StreamType::Subtitle | StreamType::Data => {
+			extract_stream(stream, output)
+		}
+		StreamType::Chapters => extract_chapters(stream, output),
+
+		// Attachments are not tracks, they are muxed from the file they are in
+		StreamType::Attachment => Ok(stream.clone()),
 	}
 }
 
-fn extract_stream(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream, ()> {
+fn extract_stream(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream> {
 	let path = output.join(&stream.id).with_extension("mkv");
 
 	let raw = path.with_extension("temp.raw");
 
-	let cmd = Command::new("mkvextract")
-		.arg(&stream.path)
-		.arg("tracks")
-		.arg(format!("{}:{}", stream.index, raw.to_str().unwrap()))
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to extract stream: {}", err);
-		return Err(());
-	}
+	tools::run(
+		tools::MKVEXTRACT
+			.command()
+			.arg(&stream.path)
+			.arg("tracks")
+			.arg(format!("{}:{}", stream.index, raw.to_str().unwrap())),
+	)?;
 
-	if stream.codec.as_deref() == Some("dvd_subtitle") {
+	// mkvmerge exits with 1 if there were only warnings
+	if stream.codec == Some(Codec::DvdSubtitle) {
 		let idx = raw.with_extension("idx");
 		let sub = raw.with_extension("sub");
 
-		let cmd = Command::new("mkvmerge")
-			.arg("-o")
-			.arg(&path)
-			.arg(&idx)
-			.arg(&sub)
-			.execute();
-
-		if let Err(err) = cmd {
-			logging::error!("Failed to create MKV: {}", err);
-			return Err(());
-		}
-
-		let cmd = cmd.unwrap();
-
-		match cmd {
-			Some(0) => {}
-			Some(1) => {}
-			_ => {
-				logging::error!("Failed to create MKV: unexpected exit code");
-				return Err(());
-			}
-		};
-
-		let err = std::fs::remove_file(&idx);
-		if let Err(err) = err {
-			logging::error!("Failed to remove file: {}", err);
-			return Err(());
-		}
-
-		let err = std::fs::remove_file(&sub);
-		if let Err(err) = err {
-			logging::error!("Failed to remove file: {}", err);
-			return Err(());
-		}
+		tools::status(
+			tools::MKVMERGE
+				.command()
+				.arg("-o")
+				.arg(&path)
+				.arg(&idx)
+				.arg(&sub),
+			&[0, 1],
+		)?;
+
+		std::fs::remove_file(&idx).map_err(|e| Error::io(&idx, e))?;
+		std::fs::remove_file(&sub).map_err(|e| Error::io(&sub, e))?;
 	} else {
-		let cmd = Command::new("mkvmerge")
-			.arg("-o")
-			.arg(&path)
-			.arg(&raw)
-			.execute();
-
-		if let Err(err) = cmd {
-			logging::error!("Failed to create MKV: {}", err);
-			return Err(());
-		}
+		tools::status(
+			tools::MKVMERGE.command().arg("-o").arg(&path).arg(&raw),
+			&[0, 1],
+		)?;
 
-		let cmd = cmd.unwrap();
-
-		match cmd {
-			Some(0) => {}
-			Some(1) => {}
-			_ => {
-				logging::error!("Failed to create MKV: unexpected exit code");
-				return Err(());
-			}
-		};
-
-		let err = std::fs::remove_file(&raw);
-		if let Err(err) = err {
-			logging::error!("Failed to remove file: {}", err);
-			return Err(());
-		}
+		std::fs::remove_file(&raw).map_err(|e| Error::io(&raw, e))?;
 	}
 
 	let mut new = stream.clone();
@@ -105,31 +70,20 @@ fn extract_stream(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream, ()
 	Ok(new)
 }
 
-fn extract_chapters(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream, ()> {
+fn extract_chapters(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream> {
 	let path = output.join(&stream.id).with_extension("txt");
 
-	let cmd = Command::new("ffmpeg")
-		.arg("-i")
-		.arg(&stream.path)
-		.arg("-f")
-		.arg("ffmetadata")
-		.arg("-y")
-		.arg("-")
-		.output();
-
-	let cmd = utils::check_output(cmd);
-	if let Err(err) = cmd {
-		logging::error!("Failed to extract chapters: {}", err);
-		return Err(());
-	}
-
-	let chapters = String::from_utf8(cmd.unwrap().stdout);
-	if let Err(err) = chapters {
-		logging::error!("Failed to decode ffmpeg output: {}", err);
-		return Err(());
-	}
+	let chapters = tools::output(
+		tools::FFMPEG
+			.command()
+			.arg("-i")
+			.arg(&stream.path)
+			.arg("-f")
+			.arg("ffmetadata")
+			.arg("-y")
+			.arg("-"),
+	)?;
 
-	let chapters = chapters.unwrap();
 	let mut new = String::new();
 
 	// Remove chapter names
@@ -143,11 +97,7 @@ fn extract_chapters(stream: &mkv::Stream, output: &Path) -> Result<mkv::Stream,
 		new.push('\n');
 	}
 
-	let err = std::fs::write(&path, new);
-	if let Err(err) = err {
-		logging::error!("Failed to write chapters: {}", err);
-		return Err(());
-	}
+	std::fs::write(&path, new).map_err(|e| Error::io(&path, e))?;
 
 	let mut new = stream.clone();
 	new.path = path;
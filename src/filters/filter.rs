@@ -2,273 +2,478 @@ use std::path::{Path, PathBuf};
 
 use json::JsonValue;
 
-use crate::{logging, mkv, utils};
+use crate::{
+	codec::Codec,
+	config::{Filter, Selector},
+	error::{Error, Result},
+	mkv::{self, StreamType},
+	timestamp::Timestamp,
+	tools::{self, Tool},
+	utils,
+};
 
 use super::{avisynth, dolby, encode, extract, offset, pitch, speed, tempo, vapoursynth};
 
-pub fn run(cfg: &JsonValue, stream: &mkv::Stream, working: &Path) -> Result<mkv::Stream, ()> {
-	let filters = &cfg["filters"];
+type Action<'a> = Box<dyn FnOnce(&mkv::Stream, &Path) -> Result<mkv::Stream> + 'a>;
 
+pub struct Stage<'a> {
+	pub name: String,
+	tools: Vec<Tool>,
+	options: Vec<String>,
+	action: Action<'a>,
+}
+
+impl<'a> Stage<'a> {
+	fn new<F>(name: String, action: F) -> Self
+	where
+		F: FnOnce(&mkv::Stream, &Path) -> Result<mkv::Stream> + 'a,
+	{
+		Self {
+			name,
+			tools: Vec::new(),
+			options: Vec::new(),
+			action: Box::new(action),
+		}
+	}
+
+	// The tools the stage runs, their versions are part of the fingerprint
+	fn uses(mut self, tools: &[Tool]) -> Self {
+		self.tools.extend_from_slice(tools);
+		self
+	}
+
+	// Options the result depends on, that are not part of the name
+	fn depends(mut self, options: String) -> Self {
+		self.options.push(options);
+		self
+	}
+
+	fn fingerprint(&self, previous: &str) -> String {
+		let versions = self.tools.iter().map(tools::version).collect::<Vec<_>>();
+
+		let mut parts = vec![previous, &self.name];
+		parts.extend(self.options.iter().map(String::as_str));
+		parts.extend(versions.iter().map(String::as_str));
+
+		utils::fingerprint(&parts)
+	}
+}
+
+// Stages of each entry have their own directory, entries can select the same stream
+fn directory(working: &Path, entry: &str, stream: &mkv::Stream) -> PathBuf {
+	working
+		.join(&stream.id)
+		.join(entry.replace(['/', '\\'], "-"))
+}
+
+// On failure, the error carries the name of the stage that failed as context
+pub fn run(
+	cfg: &Selector,
+	entry: &str,
+	stream: &mkv::Stream,
+	working: &Path,
+) -> Result<mkv::Stream> {
 	// Create working directory
-	let dir = working.join(&stream.id);
+	let dir = directory(working, entry, stream);
+
+	std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e).context("setup"))?;
+
+	let stages = plan(cfg, stream, working).map_err(|e| e.context("filter configuration"))?;
+	let fingerprints = fingerprints(stream, &stages);
+
+	let (start, mut current) = resume(entry, stream, working, &fingerprints);
 
-	let err = std::fs::create_dir_all(&dir);
-	if let Err(err) = err {
-		let name = dir.to_str().unwrap();
+	for (i, stage) in stages.into_iter().enumerate().skip(start) {
+		let num = i as u32 + 1;
+		let name = format!("Stage{} ({})", num, stage.name);
 
-		logging::error!("Failed to create directory {}: {}", name, err);
-		return Err(());
+		current = run_stage(&current, &dir, num, &fingerprints[i], stage.action)
+			.map_err(|e| e.context(name))?;
 	}
 
-	// Extract the stream
-	let mut stage = 1;
-	let mut current = run_stage(stream, &dir, stage, extract::run)?;
+	Ok(current)
+}
 
-	// Normalize Dolby audio, unless the stream is copied
-	for filter in filters.members() {
-		let name = &filter["$type"];
+pub fn tools(cfg: &Selector) -> Vec<Tool> {
+	let mut result = Vec::<Tool>::new();
 
-		if name == "encode" {
-			let newcodec = &filter["codec"];
+	tools::add(&mut result, extract::TOOLS);
 
-			if current.codec.as_deref() == Some("ac3") && newcodec != "copy" {
-				stage += 1;
-				current = run_stage(&current, &dir, stage, dolby::normalize)?;
+	for filter in &cfg.filters {
+		match filter {
+			Filter::Encode { .. } => {
+				tools::add(&mut result, dolby::TOOLS);
+				tools::add(&mut result, encode::TOOLS);
 			}
+			Filter::Offset { .. } => {}
+			Filter::VapourSynth { filter } => tools::add(&mut result, &vapoursynth::tools(filter)),
+			Filter::AviSynth { filter } => tools::add(&mut result, &avisynth::tools(filter)),
+			Filter::Speed { .. } => tools::add(&mut result, speed::TOOLS),
+			Filter::Tempo { .. } => tools::add(&mut result, tempo::TOOLS),
+			Filter::Pitch { .. } => tools::add(&mut result, pitch::TOOLS),
 		}
 	}
 
-	// Run other filters
-	for filter in filters.members() {
-		let name = &filter["$type"];
-		let path = String::from(working.to_str().unwrap());
+	result
+}
 
-		if name == "offset" {
-			let mut secs = Ok(0.0);
+// Checks the files filters depend on, without running anything
+//
+// Paths with builtins like $(working)$ are only known per item, they are checked with its config.
+pub fn check(cfg: &Selector) -> Result<()> {
+	for filter in &cfg.filters {
+		match filter {
+			Filter::VapourSynth { filter: path } | Filter::AviSynth { filter: path }
+				if path.to_str().unwrap().contains("$(") => {}
+			Filter::VapourSynth { filter: path } => {
+				template(filter.name(), path, vapoursynth::PLACEHOLDERS)?
+			}
+			Filter::AviSynth { filter: path } => {
+				template(filter.name(), path, avisynth::PLACEHOLDERS)?
+			}
+			_ => {}
+		}
+	}
 
-			for entry in filter.entries() {
-				let key = entry.0;
-				let value = entry.1;
+	Ok(())
+}
 
-				let regex = fnmatch_regex::glob_to_regex(format!("*{key}*").as_str());
-				if regex.is_err() {
-					continue;
-				}
+fn template(name: &str, path: &Path, known: &[&str]) -> Result<()> {
+	let template =
+		std::fs::read_to_string(path).map_err(|e| Error::filter(name, Error::io(path, e)))?;
 
-				let regex = regex.unwrap();
-				if regex.is_match(&path) {
-					secs = value.as_str().unwrap_or("0.0").parse::<f32>();
-					break;
-				}
-			}
+	if let Some(placeholder) = placeholders(&template).find(|p| !known.contains(p)) {
+		let path = path.to_str().unwrap();
+		let reason = format!("unknown placeholder $({placeholder})$ in {path}");
 
-			stage += 1;
-			current = run_stage(&current, &dir, stage, |s, o| {
-				offset::change(s, o, secs.unwrap())
-			})?;
-		}
+		return Err(Error::filter(name, reason));
+	}
 
-		if name == "encode" && stream.streamtype != "chapters" {
-			let mut options = filter.clone();
-			options.remove("$type");
+	Ok(())
+}
 
-			stage += 1;
-			current = run_stage(&current, &dir, stage, |s, o| encode::run(s, o, &options))?;
-		}
+// The names of all $(name)$ placeholders in a template
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+	let mut rest = template;
 
-		if current.streamtype == "video" {
-			if name == "vapoursynth" {
-				let filter = PathBuf::from(filter["filter"].to_string());
+	std::iter::from_fn(move || {
+		let start = rest.find("$(")?;
+		let end = start + rest[start..].find(")$")?;
+		let placeholder = &rest[start + 2..end];
 
-				stage += 1;
-				current = run_stage(&current, &dir, stage, |s, o| {
-					vapoursynth::run(s, o, &filter)
-				})?;
-			}
+		rest = &rest[end + 2..];
+		Some(placeholder)
+	})
+}
+
+pub fn plan<'a>(cfg: &'a Selector, stream: &mkv::Stream, working: &Path) -> Result<Vec<Stage<'a>>> {
+	let mut stages = Vec::<Stage>::new();
 
-			if name == "avisynth" {
-				let filter = PathBuf::from(filter["filter"].to_string());
+	// Attachments are muxed from the file they are in, nothing applies to them
+	if stream.streamtype == StreamType::Attachment {
+		return Ok(stages);
+	}
 
-				stage += 1;
-				current = run_stage(&current, &dir, stage, |s, o| avisynth::run(s, o, &filter))?;
+	// Extract the stream
+	let stage = Stage::new(String::from("extract"), extract::run);
+	stages.push(stage.uses(extract::TOOLS));
+
+	// Normalize Dolby audio, unless the stream is copied
+	for filter in &cfg.filters {
+		if let Filter::Encode { codec, .. } = filter {
+			if stream.codec == Some(Codec::Ac3) && codec != "copy" {
+				let stage = Stage::new(String::from("dolby"), dolby::normalize);
+				stages.push(stage.uses(dolby::TOOLS));
 			}
+		}
+	}
 
-			if name == "speed" {
-				let framerate = &filter["framerate"];
-				if framerate.is_null() {
-					logging::error!("Missing framerate!");
-					return Err(());
-				}
+	// Run other filters
+	for filter in &cfg.filters {
+		let name = filter.name();
+		let path = String::from(working.to_str().unwrap());
 
-				let framerate = utils::framerate(framerate.as_str().unwrap());
+		match filter {
+			Filter::Offset { offsets } => {
+				let mut secs = Timestamp::ZERO;
+
+				for (key, value) in offsets {
+					let regex = fnmatch_regex::glob_to_regex(format!("*{key}*").as_str());
+					if regex.is_err() {
+						continue;
+					}
+
+					let regex = regex.unwrap();
+					if regex.is_match(&path) {
+						secs = *value;
+						break;
+					}
+				}
 
-				stage += 1;
-				current = run_stage(&current, &dir, stage, |s, o| {
-					speed::change_video(s, o, framerate)
-				})?;
+				stages.push(Stage::new(format!("offset ({secs}s)"), move |s, o| {
+					offset::change(s, o, secs)
+				}));
 			}
-		}
+			Filter::Encode { codec, options } if stream.streamtype != StreamType::Chapters => {
+				// The options as they are written in the configuration
+				let mut json = JsonValue::new_object();
+				for (key, value) in options {
+					json[key.as_str()] = value.as_str().into();
+				}
+
+				let stage = Stage::new(format!("encode ({codec})"), move |s, o| {
+					encode::run(s, o, codec, options)
+				});
 
-		if current.streamtype == "audio" && name == "speed" {
-			let infps = &filter["input"];
-			if infps.is_null() {
-				logging::error!("Missing input framerate!");
-				return Err(());
+				stages.push(stage.uses(encode::TOOLS).depends(json.dump()));
 			}
+			Filter::Encode { .. } => {}
+			Filter::VapourSynth { filter } if stream.streamtype == StreamType::Video => {
+				let name = format!("vapoursynth ({})", filter.to_str().unwrap());
 
-			let outfps = &filter["output"];
-			if outfps.is_null() {
-				logging::error!("Missing output framerate!");
-				return Err(());
+				let tools = vapoursynth::tools(filter);
+				let template = std::fs::read_to_string(filter).unwrap_or_default();
+
+				let stage = Stage::new(name, move |s, o| vapoursynth::run(s, o, filter));
+				stages.push(stage.uses(&tools).depends(template));
 			}
+			Filter::AviSynth { filter } if stream.streamtype == StreamType::Video => {
+				let name = format!("avisynth ({})", filter.to_str().unwrap());
 
-			let infps = utils::framerate(infps.as_str().unwrap());
-			let outfps = utils::framerate(outfps.as_str().unwrap());
+				let tools = avisynth::tools(filter);
+				let template = std::fs::read_to_string(filter).unwrap_or_default();
 
-			stage += 1;
-			current = run_stage(&current, &dir, stage, |s, o| {
-				speed::change_audio(s, o, infps, outfps)
-			})?;
-		}
+				let stage = Stage::new(name, move |s, o| avisynth::run(s, o, filter));
+				stages.push(stage.uses(&tools).depends(template));
+			}
+			Filter::VapourSynth { .. } | Filter::AviSynth { .. } => {}
+			Filter::Speed { framerate, .. } if stream.streamtype == StreamType::Video => {
+				let framerate =
+					framerate.ok_or_else(|| Error::filter(name, "missing framerate"))?;
+				let name = format!("speed (to {}/{})", framerate.0, framerate.1);
+
+				let stage = Stage::new(name, move |s, o| speed::change_video(s, o, framerate));
+				stages.push(stage.uses(speed::TOOLS));
+			}
+			Filter::Speed { input, output, .. } if stream.streamtype == StreamType::Audio => {
+				let (infps, outfps) = framerates(name, *input, *output)?;
 
-		if current.streamtype == "audio" && name == "tempo" {
-			let infps = &filter["input"];
-			if infps.is_null() {
-				logging::error!("Missing input framerate!");
-				return Err(());
+				let stage = Stage::new(describe(name, infps, outfps), move |s, o| {
+					speed::change_audio(s, o, infps, outfps)
+				});
+				stages.push(stage.uses(speed::TOOLS));
 			}
+			Filter::Speed { input, output, .. } if stream.streamtype == StreamType::Subtitle => {
+				let (infps, outfps) = framerates(name, *input, *output)?;
 
-			let outfps = &filter["output"];
-			if outfps.is_null() {
-				logging::error!("Missing output framerate!");
-				return Err(());
+				let stage = Stage::new(describe(name, infps, outfps), move |s, o| {
+					speed::change_subtitles(s, o, infps, outfps)
+				});
+				stages.push(stage.uses(speed::TOOLS));
 			}
+			Filter::Speed { input, output, .. } if stream.streamtype == StreamType::Chapters => {
+				let (infps, outfps) = framerates(name, *input, *output)?;
 
-			let infps = utils::framerate(infps.as_str().unwrap());
-			let outfps = utils::framerate(outfps.as_str().unwrap());
+				stages.push(Stage::new(describe(name, infps, outfps), move |s, o| {
+					speed::change_chapters(s, o, infps, outfps)
+				}));
+			}
+			Filter::Speed { .. } => {}
+			Filter::Tempo { input, output } if stream.streamtype == StreamType::Audio => {
+				let (infps, outfps) = (*input, *output);
+
+				let stage = Stage::new(describe(name, infps, outfps), move |s, o| {
+					tempo::change(s, o, infps, outfps)
+				});
+				stages.push(stage.uses(tempo::TOOLS));
+			}
+			Filter::Pitch { input, output } if stream.streamtype == StreamType::Audio => {
+				let (infps, outfps) = (*input, *output);
 
-			stage += 1;
-			current = run_stage(&current, &dir, stage, |s, o| {
-				tempo::change(s, o, infps, outfps)
-			})?;
+				let stage = Stage::new(describe(name, infps, outfps), move |s, o| {
+					pitch::change(s, o, infps, outfps)
+				});
+				stages.push(stage.uses(pitch::TOOLS));
+			}
+			Filter::Tempo { .. } | Filter::Pitch { .. } => {}
 		}
+	}
 
-		if current.streamtype == "audio" && name == "pitch" {
-			let infps = &filter["input"];
-			if infps.is_null() {
-				logging::error!("Missing input framerate!");
-				return Err(());
-			}
+	Ok(stages)
+}
 
-			let outfps = &filter["output"];
-			if outfps.is_null() {
-				logging::error!("Missing output framerate!");
-				return Err(());
-			}
+// Speed filters without a stream type in the configuration are only checked here
+fn framerates(
+	name: &str,
+	input: Option<(u32, u32)>,
+	output: Option<(u32, u32)>,
+) -> Result<((u32, u32), (u32, u32))> {
+	match (input, output) {
+		(Some(input), Some(output)) => Ok((input, output)),
+		_ => Err(Error::filter(name, "missing input or output framerate")),
+	}
+}
 
-			let infps = utils::framerate(infps.as_str().unwrap());
-			let outfps = utils::framerate(outfps.as_str().unwrap());
+fn describe(name: &str, infps: (u32, u32), outfps: (u32, u32)) -> String {
+	format!(
+		"{} ({}/{} -> {}/{})",
+		name, infps.0, infps.1, outfps.0, outfps.1
+	)
+}
 
-			stage += 1;
-			current = run_stage(&current, &dir, stage, |s, o| {
-				pitch::change(s, o, infps, outfps)
-			})?;
-		}
+pub fn fingerprints(stream: &mkv::Stream, stages: &[Stage]) -> Vec<String> {
+	// A remade input has the same streams, but it may not have the same content
+	let input = mkv::key(&stream.path).unwrap_or_default();
 
-		if current.streamtype == "subtitle" && name == "speed" {
-			let infps = &filter["input"];
-			if infps.is_null() {
-				logging::error!("Missing input framerate!");
-				return Err(());
-			}
+	let mut previous = utils::fingerprint(&[&stream.to_json().dump(), &input]);
+	let mut result = Vec::<String>::new();
 
-			let outfps = &filter["output"];
-			if outfps.is_null() {
-				logging::error!("Missing output framerate!");
-				return Err(());
-			}
+	for stage in stages {
+		previous = stage.fingerprint(&previous);
+		result.push(previous.clone());
+	}
 
-			let infps = utils::framerate(infps.as_str().unwrap());
-			let outfps = utils::framerate(outfps.as_str().unwrap());
+	result
+}
 
-			stage += 1;
-			current = run_stage(&current, &dir, stage, |s, o| {
-				speed::change_subtitles(s, o, infps, outfps)
-			})?;
+// Returns the number of stages that don't have to run again, and the stream to continue with
+pub fn resume(
+	entry: &str,
+	stream: &mkv::Stream,
+	working: &Path,
+	fingerprints: &[String],
+) -> (usize, mkv::Stream) {
+	let dir = directory(working, entry, stream);
+	let mut commits = Vec::<mkv::Stream>::new();
+
+	for (i, fingerprint) in fingerprints.iter().enumerate() {
+		match committed(&dir, i as u32 + 1, fingerprint) {
+			Some(commit) => commits.push(commit),
+			None => break,
 		}
+	}
 
-		if current.streamtype == "chapters" && name == "speed" {
-			let infps = &filter["input"];
-			if infps.is_null() {
-				logging::error!("Missing input framerate!");
-				return Err(());
-			}
+	// Inputs of earlier stages are removed once the next stage ran,
+	// so go back to the last stage whose output still exists
+	while commits.last().is_some_and(|c| !c.path.exists()) {
+		commits.pop();
+	}
 
-			let outfps = &filter["output"];
-			if outfps.is_null() {
-				logging::error!("Missing output framerate!");
-				return Err(());
-			}
+	let current = commits.last().cloned().unwrap_or_else(|| stream.clone());
+	(commits.len(), current)
+}
 
-			let infps = utils::framerate(infps.as_str().unwrap());
-			let outfps = utils::framerate(outfps.as_str().unwrap());
+fn committed(dir: &Path, stage: u32, fingerprint: &str) -> Option<mkv::Stream> {
+	let commit = dir
+		.join(format!("Stage{stage}"))
+		.join("commit")
+		.with_extension("json");
 
-			stage += 1;
-			current = run_stage(&current, &dir, stage, |s, o| {
-				speed::change_chapters(s, o, infps, outfps)
-			})?;
-		}
+	let data = std::fs::read_to_string(&commit).ok()?;
+	let mut data = json::parse(&data).ok()?;
+
+	// The configuration, the input or a tool changed since the stage ran
+	if data["version"].as_u32() != Some(mkv::VERSION)
+		|| data["fingerprint"].as_str() != Some(fingerprint)
+	{
+		return None;
 	}
 
-	Ok(current)
+	mkv::Stream::from_json(&commit, data["stream"].take()).ok()
 }
 
 fn run_stage<F>(
 	stream: &mkv::Stream,
 	working: &Path,
 	stage: u32,
+	fingerprint: &str,
 	action: F,
-) -> Result<mkv::Stream, ()>
+) -> Result<mkv::Stream>
 where
-	F: FnOnce(&mkv::Stream, &Path) -> Result<mkv::Stream, ()>,
+	F: FnOnce(&mkv::Stream, &Path) -> Result<mkv::Stream>,
 {
 	let dir = working.join(format!("Stage{stage}"));
 
-	let err = std::fs::create_dir_all(&dir);
-	if let Err(err) = err {
-		let name = dir.to_str().unwrap();
+	std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
 
-		logging::error!("Failed to create directory {}: {}", name, err);
-		return Err(());
-	}
+	// Keep the output of all tools next to the commit, for debugging
+	let new = tools::logged(&dir, || action(stream, &dir))?;
 
-	// If this stage was already committed, don't run it again
+	// Commit the new stream
 	let commit = dir.join("commit").with_extension("json");
-	if commit.exists() {
-		return mkv::Stream::load(&commit);
-	}
+	let data = json::object! {
+		version: mkv::VERSION,
+		fingerprint: fingerprint,
+		stream: new.to_json(),
+	};
 
-	let new = action(stream, &dir)?;
-
-	// Commit the new stream
-	let err = new.save(&commit);
-	if let Err(err) = err {
-		logging::error!("Failed to commit stage {}: {}", stage, err);
-		return Err(());
-	}
+	let data = json::stringify_pretty(data, 4);
+	std::fs::write(&commit, data).map_err(|e| Error::io(&commit, e))?;
 
 	// Make sure we dont clean up stage 0
-	let prev = stage - 1;
-	if prev == 0 {
+	if stage == 1 {
 		return Ok(new);
 	}
 
 	// Clean up the old stream
-	let err = stream.cleanup();
-	if let Err(err) = err {
-		logging::error!("Failed to cleanup stage {}: {}", prev, err);
-		return Err(());
-	}
+	stream.cleanup()?;
 
 	Ok(new)
 }
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::*;
+
+	fn selector(filter: Filter) -> Selector {
+		Selector {
+			streamtype: None,
+			lang: None,
+			id: None,
+			file: None,
+			missing: false,
+			filters: vec![filter],
+		}
+	}
+
+	#[test]
+	fn placeholders() {
+		let template = "clip = load('$(mkv)$')\n$(pass)$ $(d2v)$ $( broken";
+		let found = super::placeholders(template).collect::<Vec<_>>();
+		assert_eq!(found, ["mkv", "pass", "d2v"]);
+
+		assert_eq!(
+			super::placeholders("$(mkv)$$(vpy)$").collect::<Vec<_>>(),
+			["mkv", "vpy"]
+		);
+		assert_eq!(super::placeholders("no placeholders ) $").count(), 0);
+	}
+
+	#[test]
+	fn templates() {
+		let dir = tempdir::TempDir::new("videoproc").unwrap();
+		let valid = dir.path().join("valid.vpy");
+		let invalid = dir.path().join("invalid.vpy");
+
+		std::fs::write(&valid, "src('$(mkv)$')\n$(pass)$").unwrap();
+		std::fs::write(&invalid, "src('$(mkv)$', '$(input)$')").unwrap();
+
+		let filter = |path: &Path| Filter::VapourSynth {
+			filter: path.to_path_buf(),
+		};
+
+		assert!(check(&selector(filter(&valid))).is_ok());
+
+		let err = check(&selector(filter(&invalid))).unwrap_err().to_string();
+		assert!(err.contains("unknown placeholder $(input)$"), "{err}");
+
+		// Paths named after the item are checked once the item is known
+		let per_item = PathBuf::from("$(working)$/filter.vpy");
+		assert!(check(&selector(filter(&per_item))).is_ok());
+
+		let missing = dir.path().join("missing.vpy");
+		assert!(check(&selector(filter(&missing))).is_err());
+	}
+}
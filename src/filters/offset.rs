@@ -1,24 +1,24 @@
 use std::path::Path;
 
-use crate::{logging, mkv};
+use crate::{
+	error::{Error, Result},
+	logging, mkv,
+	timestamp::Timestamp,
+};
 
-pub fn change(stream: &mkv::Stream, output: &Path, seconds: f32) -> Result<mkv::Stream, ()> {
-	logging::info!("Changing offset of stream {} to {}s", stream.id, seconds);
+pub fn change(stream: &mkv::Stream, output: &Path, offset: Timestamp) -> Result<mkv::Stream> {
+	logging::info!("Changing offset of stream {} to {}s", stream.id, offset);
 
 	let path = output.join(&stream.id).with_extension(format!(
 		"offset.{}",
 		stream.path.extension().unwrap().to_str().unwrap()
 	));
 
-	let err = std::fs::copy(&stream.path, &path);
-	if let Err(err) = err {
-		logging::error!("Failed to copy file: {}", err);
-		return Err(());
-	}
+	std::fs::copy(&stream.path, &path).map_err(|e| Error::io(&path, e))?;
 
 	let mut new = stream.clone();
 	new.path = path;
-	new.offset = seconds;
+	new.offset = offset;
 
 	Ok(new)
 }
@@ -1,22 +1,25 @@
-use std::{path::Path, process::Command};
-
-use execute::Execute;
+use std::path::Path;
 
 use crate::{
+	codec::Codec,
+	error::Result,
 	logging, mkv,
+	tools::{self, Tool},
 	utils::{self, StrVec},
 };
 
+pub const TOOLS: &[Tool] = &[tools::FFMPEG];
+
 pub fn change(
 	stream: &mkv::Stream,
 	output: &Path,
 	infps: (u32, u32),
 	outfps: (u32, u32),
-) -> Result<mkv::Stream, ()> {
+) -> Result<mkv::Stream> {
 	let speedup = utils::speedup(infps, outfps);
 	let path = output.join(&stream.id).with_extension("pitch.w64");
 
-	logging::info!("Changing pitch by {}", speedup);
+	logging::info!("Changing pitch by {}/{}", speedup.0, speedup.1);
 
 	let mut args = Vec::<String>::new();
 
@@ -27,10 +30,12 @@ pub fn change(
 	args.push(format!("0:{}", stream.index));
 
 	let af = format!(
-		"asetrate={}*{},aresample,atempo=1/{}",
+		"asetrate={}*{}/{},aresample,atempo={}/{}",
 		stream.samplerate.unwrap(),
-		speedup,
-		speedup
+		speedup.0,
+		speedup.1,
+		speedup.1,
+		speedup.0
 	);
 	args.push_str("-af");
 	args.push(af);
@@ -47,19 +52,12 @@ pub fn change(
 	args.push_str("-y");
 	args.push_str(path.to_str().unwrap());
 
-	let cmd = Command::new("ffmpeg")
-		.args(args)
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run ffmpeg: {}", err);
-		return Err(());
-	}
+	tools::run(tools::FFMPEG.command().args(args))?;
 
 	let mut new = stream.clone();
 	new.path = path.clone();
 	new.index = 0;
-	new.codec = Some(String::from("pcm_f32le"));
+	new.codec = Some(Codec::PcmF32le);
 
 	Ok(new)
 }
@@ -1,21 +1,24 @@
-use std::{path::Path, process::Command};
-
-use execute::Execute;
+use std::path::Path;
 
 use crate::{
-	logging, mkv,
+	codec::Codec,
+	error::{Error, Result},
+	logging, mkv, timestamp,
+	tools::{self, Tool},
 	utils::{self, StrVec},
 };
 
+pub const TOOLS: &[Tool] = &[tools::MKVMERGE, tools::FFMPEG];
+
 pub fn change_video(
 	stream: &mkv::Stream,
 	output: &Path,
 	framerate: (u32, u32),
-) -> Result<mkv::Stream, ()> {
+) -> Result<mkv::Stream> {
 	let speedup = utils::speedup(stream.framerate.unwrap(), framerate);
 	let path = output.join(&stream.id).with_extension("speed.mkv");
 
-	logging::info!("Changing speed by {}", speedup);
+	logging::info!("Changing speed by {}/{}", speedup.0, speedup.1);
 
 	let mut args = Vec::<String>::new();
 
@@ -30,14 +33,7 @@ pub fn change_video(
 
 	args.push_str(stream.path.to_str().unwrap());
 
-	let cmd = Command::new("mkvmerge")
-		.args(args)
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run mkvmerge: {}", err);
-		return Err(());
-	}
+	tools::run(tools::MKVMERGE.command().args(args))?;
 
 	let mut new = stream.clone();
 	new.path = path.clone();
@@ -53,11 +49,11 @@ pub fn change_audio(
 	output: &Path,
 	infps: (u32, u32),
 	outfps: (u32, u32),
-) -> Result<mkv::Stream, ()> {
+) -> Result<mkv::Stream> {
 	let speedup = utils::speedup(infps, outfps);
 	let path = output.join(&stream.id).with_extension("speed.w64");
 
-	logging::info!("Changing speed by {}", speedup);
+	logging::info!("Changing speed by {}/{}", speedup.0, speedup.1);
 
 	let mut args = Vec::<String>::new();
 
@@ -68,9 +64,10 @@ pub fn change_audio(
 	args.push(format!("0:{}", stream.index));
 
 	let af = format!(
-		"asetrate={}*{},aresample",
+		"asetrate={}*{}/{},aresample",
 		stream.samplerate.unwrap(),
-		speedup
+		speedup.0,
+		speedup.1
 	);
 	args.push_str("-af");
 	args.push(af);
@@ -87,19 +84,12 @@ pub fn change_audio(
 	args.push_str("-y");
 	args.push_str(path.to_str().unwrap());
 
-	let cmd = Command::new("ffmpeg")
-		.args(args)
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run ffmpeg: {}", err);
-		return Err(());
-	}
+	tools::run(tools::FFMPEG.command().args(args))?;
 
 	let mut new = stream.clone();
 	new.path = path.clone();
 	new.index = 0;
-	new.codec = Some(String::from("pcm_f32le"));
+	new.codec = Some(Codec::PcmF32le);
 	new.offset /= speedup;
 	new.duration /= speedup;
 
@@ -111,11 +101,11 @@ pub fn change_subtitles(
 	output: &Path,
 	infps: (u32, u32),
 	outfps: (u32, u32),
-) -> Result<mkv::Stream, ()> {
+) -> Result<mkv::Stream> {
 	let speedup = utils::speedup(infps, outfps);
 	let path = output.join(&stream.id).with_extension("speed.mkv");
 
-	logging::info!("Changing speed by {}", speedup);
+	logging::info!("Changing speed by {}/{}", speedup.0, speedup.1);
 
 	let mut args = Vec::<String>::new();
 
@@ -126,7 +116,7 @@ pub fn change_subtitles(
 	args.push(format!("0:{}", stream.index));
 
 	args.push_str("-bsf");
-	args.push(format!("setts=TS/{speedup}"));
+	args.push(format!("setts=TS*{}/{}", speedup.1, speedup.0));
 
 	args.push_str("-codec");
 	args.push_str("copy");
@@ -134,14 +124,7 @@ pub fn change_subtitles(
 	args.push_str("-y");
 	args.push_str(path.to_str().unwrap());
 
-	let cmd = Command::new("ffmpeg")
-		.args(args)
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run ffmpeg: {}", err);
-		return Err(());
-	}
+	tools::run(tools::FFMPEG.command().args(args))?;
 
 	let mut new = stream.clone();
 	new.path = path.clone();
@@ -157,44 +140,34 @@ pub fn change_chapters(
 	output: &Path,
 	infps: (u32, u32),
 	outfps: (u32, u32),
-) -> Result<mkv::Stream, ()> {
+) -> Result<mkv::Stream> {
 	let speedup = utils::speedup(infps, outfps);
 	let path = output.join(&stream.id).with_extension("speed.txt");
 
-	logging::info!("Changing speed by {}", speedup);
-
-	let chapters = std::fs::read_to_string(&stream.path);
-	if let Err(err) = chapters {
-		logging::error!("Failed to read chapters: {}", err);
-		return Err(());
-	}
+	logging::info!("Changing speed by {}/{}", speedup.0, speedup.1);
 
+	let chapters = std::fs::read_to_string(&stream.path).map_err(|e| Error::io(&stream.path, e))?;
 	let mut new = String::new();
-	let chapters = chapters.unwrap();
 
-	// Modify the timebase value to manipulate the timestamps
-	// Changing the timebase changes start / stop at the same time
+	// The timebase stays as it is, ffmpeg reads it as two 32 bit integers,
+	// so the start and end of every chapter are scaled instead
 	for line in chapters.split('\n') {
-		if line.starts_with("TIMEBASE") {
-			let split = line.split('=').collect::<Vec<&str>>();
-			let tb = utils::framerate(split[1]);
-
-			let num = tb.0;
-			let den = (tb.1 as f32 * speedup).round() as u32;
-
-			new.push_str(format!("TIMEBASE={num}/{den}").as_str());
-		} else {
-			new.push_str(line);
+		let time = line
+			.split_once('=')
+			.filter(|(key, _)| *key == "START" || *key == "END")
+			.and_then(|(key, value)| Some((key, value.trim().parse::<i64>().ok()?)));
+
+		match time {
+			Some((key, ticks)) => {
+				new.push_str(&format!("{key}={}", timestamp::slow(ticks, speedup)))
+			}
+			None => new.push_str(line),
 		}
 
 		new.push('\n');
 	}
 
-	let err = std::fs::write(&path, new);
-	if let Err(err) = err {
-		logging::error!("Failed to write chapters: {}", err);
-		return Err(());
-	}
+	std::fs::write(&path, new).map_err(|e| Error::io(&path, e))?;
 
 	let mut new = stream.clone();
 	new.path = path;
@@ -1,22 +1,25 @@
-use std::{path::Path, process::Command};
-
-use execute::Execute;
+use std::path::Path;
 
 use crate::{
+	codec::Codec,
+	error::Result,
 	logging, mkv,
+	tools::{self, Tool},
 	utils::{self, StrVec},
 };
 
+pub const TOOLS: &[Tool] = &[tools::FFMPEG];
+
 pub fn change(
 	stream: &mkv::Stream,
 	output: &Path,
 	infps: (u32, u32),
 	outfps: (u32, u32),
-) -> Result<mkv::Stream, ()> {
+) -> Result<mkv::Stream> {
 	let speedup = utils::speedup(infps, outfps);
 	let path = output.join(&stream.id).with_extension("tempo.w64");
 
-	logging::info!("Changing tempo by {}", speedup);
+	logging::info!("Changing tempo by {}/{}", speedup.0, speedup.1);
 
 	let mut args = Vec::<String>::new();
 
@@ -26,7 +29,7 @@ pub fn change(
 	args.push_str("-map");
 	args.push(format!("0:{}", stream.index));
 
-	let af = format!("atempo={speedup}");
+	let af = format!("atempo={}/{}", speedup.0, speedup.1);
 	args.push_str("-af");
 	args.push(af);
 
@@ -36,19 +39,12 @@ pub fn change(
 	args.push_str("-y");
 	args.push_str(path.to_str().unwrap());
 
-	let cmd = Command::new("ffmpeg")
-		.args(args)
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run ffmpeg: {}", err);
-		return Err(());
-	}
+	tools::run(tools::FFMPEG.command().args(args))?;
 
 	let mut new = stream.clone();
 	new.path = path.clone();
 	new.index = 0;
-	new.codec = Some(String::from("pcm_f32le"));
+	new.codec = Some(Codec::PcmF32le);
 	new.offset /= speedup;
 	new.duration /= speedup;
 
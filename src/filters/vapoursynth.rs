@@ -1,18 +1,36 @@
-use std::{
-	path::Path,
-	process::{Command, Stdio},
+use std::{io, path::Path, process::Stdio};
+
+use crate::{
+	codec::Codec,
+	error::{Error, Result},
+	logging, mkv,
+	timestamp::Timestamp,
+	tools::{self, Tool},
+	utils,
 };
 
-use execute::Execute;
+// Placeholders in templates that are replaced before the script runs
+pub const PLACEHOLDERS: &[&str] = &["mkv", "vpy", "mpg", "d2v", "pass"];
 
-use crate::{logging, mkv, utils};
+pub fn tools(filter: &Path) -> Vec<Tool> {
+	let mut tools = vec![tools::VSPIPE, tools::FFMPEG];
 
-pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::Stream, ()> {
-	if !filter.exists() {
-		logging::error!("Filter not found!");
-		return Err(());
+	let template = std::fs::read_to_string(filter).unwrap_or_default();
+
+	if template.contains("$(d2v)$") || template.contains("$(mpg)$") {
+		tools.push(tools::MKVEXTRACT);
+	}
+
+	if template.contains("$(d2v)$") {
+		tools.push(tools::D2VWITCH);
 	}
 
+	tools
+}
+
+pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::Stream> {
+	let template = std::fs::read_to_string(filter).map_err(|e| Error::io(filter, e))?;
+
 	let name = filter.file_stem().unwrap().to_str().unwrap();
 	logging::info!("Filtering stream using VapourSynth filter {}", name);
 
@@ -22,44 +40,32 @@ pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::St
 	let mpg = script.with_extension("vpy.mpg");
 	let d2v = script.with_extension("vpy.d2v");
 
-	let template = std::fs::read_to_string(filter);
-	if let Err(err) = template {
-		logging::error!("Failed to read filter: {}", err);
-		return Err(());
-	}
-
-	let mut template = template.unwrap();
+	let mut template = template;
 	template = template.replace("$(mkv)$", stream.path.to_str().unwrap());
 	template = template.replace("$(vpy)$", filter.to_str().unwrap());
 
 	// If requested extract the MPEG-2 stream
 	if template.contains("$(d2v)$") || template.contains("$(mpg)$") {
-		let cmd = Command::new("mkvextract")
-			.arg(stream.path.to_str().unwrap())
-			.arg("tracks")
-			.arg(format!("0:{}", &mpg.to_str().unwrap()))
-			.execute_check_exit_status_code(0);
-
-		if let Err(err) = cmd {
-			logging::error!("Failed to extract video stream: {}", err);
-			return Err(());
-		}
+		tools::run(
+			tools::MKVEXTRACT
+				.command()
+				.arg(stream.path.to_str().unwrap())
+				.arg("tracks")
+				.arg(format!("0:{}", &mpg.to_str().unwrap())),
+		)?;
 
 		template = template.replace("$(mpg)$", mpg.to_str().unwrap());
 	}
 
 	// If requested create a D2V Index for MPEG2 streams
 	if template.contains("$(d2v)$") {
-		let cmd = Command::new("d2vwitch")
-			.arg("--output")
-			.arg(d2v.to_str().unwrap())
-			.arg(mpg.to_str().unwrap())
-			.execute_check_exit_status_code(0);
-
-		if let Err(err) = cmd {
-			logging::error!("Failed to create D2V index: {}", err);
-			return Err(());
-		}
+		tools::run(
+			tools::D2VWITCH
+				.command()
+				.arg("--output")
+				.arg(d2v.to_str().unwrap())
+				.arg(mpg.to_str().unwrap()),
+		)?;
 
 		template = template.replace("$(d2v)$", d2v.to_str().unwrap());
 	}
@@ -69,54 +75,36 @@ pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::St
 		let script = script.with_extension("pass1.vpy");
 		let p1 = template.replace("$(pass)$", "1");
 
-		let err = std::fs::write(&script, p1);
-		if let Err(err) = err {
-			logging::error!("Failed to write vapoursynth script: {}", err);
-			return Err(());
-		}
+		std::fs::write(&script, p1).map_err(|e| Error::io(&script, e))?;
 
-		let vspipe = Command::new("vspipe")
-			.current_dir(script.parent().unwrap())
-			.arg(script.to_str().unwrap())
-			.arg("-")
-			.execute_check_exit_status_code(0);
+		let mut pass = tools::spawn(
+			tools::VSPIPE
+				.command()
+				.current_dir(script.parent().unwrap())
+				.arg(script.to_str().unwrap())
+				.arg("-"),
+		)?;
 
-		if let Err(err) = vspipe {
-			logging::error!("Failed to run vspipe: {}", err);
-			return Err(());
-		}
+		// The first pass only collects statistics, its video is not needed
+		let _ = io::copy(&mut pass.stdout().unwrap(), &mut io::sink());
+		pass.wait(&[0])?;
 
-		let err = std::fs::remove_file(script);
-		if let Err(err) = err {
-			logging::error!("Failed to remove file: {}", err);
-			return Err(());
-		}
+		std::fs::remove_file(&script).map_err(|e| Error::io(&script, e))?;
 
 		template = template.replace("$(pass)$", "2");
 	}
 
-	let err = std::fs::write(&script, template);
-	if let Err(err) = err {
-		logging::error!("Failed to write vapoursynth script: {}", err);
-		return Err(());
-	}
-
-	let vspipe = Command::new("vspipe")
-		.current_dir(script.parent().unwrap())
-		.arg(script.to_str().unwrap())
-		.arg("-")
-		.arg("-c")
-		.arg("y4m")
-		.stdout(Stdio::piped())
-		.stderr(Stdio::null())
-		.spawn();
-
-	if let Err(err) = vspipe {
-		logging::error!("Failed to run vspipe: {}", err);
-		return Err(());
-	}
+	std::fs::write(&script, template).map_err(|e| Error::io(&script, e))?;
 
-	let mut vspipe = vspipe.unwrap();
+	let mut vspipe = tools::spawn(
+		tools::VSPIPE
+			.command()
+			.current_dir(script.parent().unwrap())
+			.arg(script.to_str().unwrap())
+			.arg("-")
+			.arg("-c")
+			.arg("y4m"),
+	)?;
 
 	let mut args = vec!["-i", "pipe:", "-codec", "ffv1", "-map", "0"];
 
@@ -128,34 +116,24 @@ pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::St
 	args.push("-y");
 	args.push(path.to_str().unwrap());
 
-	let ffmpeg = Command::new("ffmpeg")
-		.current_dir(script.parent().unwrap())
-		.args(args)
-		.stdin(Stdio::from(vspipe.stdout.take().unwrap()))
-		.output();
+	let ffmpeg = tools::run(
+		tools::FFMPEG
+			.command()
+			.current_dir(script.parent().unwrap())
+			.args(args)
+			.stdin(Stdio::from(vspipe.stdout().unwrap())),
+	);
 
-	let ffmpeg = utils::check_output(ffmpeg);
 	if let Err(err) = ffmpeg {
-		let _ = vspipe.kill();
-		logging::error!("Failed to run ffmpeg: {}", err);
-		return Err(());
+		vspipe.kill();
+		let _ = vspipe.wait(&[0]);
+		return Err(err);
 	}
 
-	let probe = Command::new("vspipe").arg("-i").arg(&script).output();
-
-	if let Err(err) = probe {
-		logging::error!("Failed to run vspipe: {}", err);
-		return Err(());
-	}
+	vspipe.wait(&[0])?;
 
-	let probe = probe.unwrap();
-	let probe = String::from_utf8(probe.stdout);
-	if let Err(err) = probe {
-		logging::error!("Failed to decode vspipe output: {}", err);
-		return Err(());
-	}
+	let probe = tools::output(tools::VSPIPE.command().arg("-i").arg(&script))?;
 
-	let probe = probe.unwrap();
 	let mut frames = 0;
 	let mut framerate = (0u32, 0u32);
 
@@ -164,12 +142,7 @@ pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::St
 
 		if split[0] == "Frames" {
 			let fr = split[1].trim().parse::<u32>();
-			if let Err(err) = fr {
-				logging::error!("Failed to parse frame count: {}", err);
-				return Err(());
-			}
-
-			frames = fr.unwrap();
+			frames = fr.map_err(|e| Error::parse(&script, format!("invalid frame count: {e}")))?;
 		}
 
 		if split[0] == "FPS" {
@@ -178,35 +151,23 @@ pub fn run(stream: &mkv::Stream, output: &Path, filter: &Path) -> Result<mkv::St
 		}
 	}
 
-	let duration = frames as f32 / (framerate.0 as f32 / framerate.1 as f32);
-	let speedup = stream.duration / duration;
+	let duration = Timestamp::from_frames(frames as u64, framerate);
+	let speedup = stream.duration.ratio(duration);
 
-	let err = std::fs::remove_file(script);
-	if let Err(err) = err {
-		logging::error!("Failed to remove file: {}", err);
-		return Err(());
-	}
+	std::fs::remove_file(&script).map_err(|e| Error::io(&script, e))?;
 
 	if mpg.exists() {
-		let err = std::fs::remove_file(mpg);
-		if let Err(err) = err {
-			logging::error!("Failed to remove file: {}", err);
-			return Err(());
-		}
+		std::fs::remove_file(&mpg).map_err(|e| Error::io(&mpg, e))?;
 	}
 
 	if d2v.exists() {
-		let err = std::fs::remove_file(d2v);
-		if let Err(err) = err {
-			logging::error!("Failed to remove file: {}", err);
-			return Err(());
-		}
+		std::fs::remove_file(&d2v).map_err(|e| Error::io(&d2v, e))?;
 	}
 
 	let mut new = stream.clone();
 	new.path = path;
 	new.index = 0;
-	new.codec = Some(String::from("ffv1"));
+	new.codec = Some(Codec::Ffv1);
 	new.offset /= speedup;
 	new.duration /= speedup;
 
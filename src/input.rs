@@ -1,144 +1,792 @@
 use std::{
+	collections::BTreeMap,
 	io,
 	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Mutex,
+	},
+	time::UNIX_EPOCH,
 };
 
-use crate::{config, filters, logging, mkv, select};
-use json::JsonValue;
+use regex::Regex;
 
-pub fn process(config: &Path, input: &Path, working: &Path, output: &Path) -> Result<(), ()> {
-	let cfg = config::load(config);
+use crate::{
+	config::{self, Builtins, Config, Filter, Join, Layout},
+	disc,
+	error::{Error, Result},
+	filters, logging, mkv, naming,
+	scheduler::Scheduler,
+	select, tools, utils,
+};
 
-	for entry in cfg {
-		let name = entry.0;
-		let data = entry.1;
+pub enum Status {
+	Processed,
+	Exists,
+	Failed(Error),
+	Aborted,
+}
 
-		let path = input.join(&name);
-		if !path.exists() || !path.is_dir() {
-			continue;
-		}
+pub struct Report {
+	// The input folder, the item is empty if the folder could not be processed at all
+	pub dir: String,
+	pub item: String,
+	pub status: Status,
+}
 
-		let wdir = working.join(&name);
-		let odir = output.join(&name);
+struct Item<'a> {
+	dir: &'a str,
+	cfg: &'a Config,
+	name: String,
+	path: PathBuf,
+	working: PathBuf,
 
-		let err = std::fs::create_dir_all(&wdir);
-		if let Err(err) = err {
-			let name = wdir.to_str().unwrap();
+	// The output directory, the configuration names the output inside of it
+	output: PathBuf,
+}
 
-			logging::error!("Failed to create directory {}: {}", name, err);
-			return Err(());
+// Outputs of the items of this run, so items can't overwrite each other
+type Claims = Mutex<BTreeMap<PathBuf, String>>;
+
+impl Item<'_> {
+	// The configuration with the builtins of this item filled in
+	fn config(&self) -> Result<Config> {
+		let builtins = Builtins {
+			item: &self.name,
+			input: &self.path,
+			working: &self.working,
+		};
+
+		self.cfg
+			.item(&builtins)
+			.map_err(|e| e.context("configuration"))
+	}
+
+	// Claims the output before processing, unless the template needs the processed streams
+	//
+	// Filters can change what those placeholders are filled in with, e.g. the codec or the
+	// resolution, so items that share such a name only collide once their filters ran.
+	fn early_output(&self, cfg: &Config, claims: &Claims) -> Result<Option<PathBuf>> {
+		if naming::needs_streams(cfg) {
+			return Ok(None);
 		}
 
-		let err = std::fs::create_dir_all(&odir);
-		if let Err(err) = err {
-			let name = odir.to_str().unwrap();
+		self.output(cfg, &[], claims).map(Some)
+	}
 
-			logging::error!("Failed to create directory {}: {}", name, err);
-			return Err(());
+	// Where the output goes, the processed streams fill in the placeholders of the template
+	fn output(&self, cfg: &Config, streams: &[mkv::Stream], claims: &Claims) -> Result<PathBuf> {
+		let path = self
+			.output
+			.join(naming::path(cfg, self.dir, &self.name, streams)?);
+
+		let owner = format!("{}/{}", self.dir, self.name);
+		let mut claims = claims.lock().unwrap();
+
+		match claims.get(&path) {
+			Some(other) if *other != owner => Err(Error::Collision {
+				path,
+				owner: Some(other.clone()),
+			}),
+			_ => {
+				claims.insert(path.clone(), owner);
+				Ok(path)
+			}
 		}
+	}
 
-		logging::scope("config", &name, || process_dir(&data, &path, &wdir, &odir))?;
+	// The output of the last run, older versions always named it after the item
+	fn previous(&self, cfg: &Config) -> Option<PathBuf> {
+		match recorded(&self.working) {
+			Some((_, Some(path))) => Some(path),
+			_ if cfg.output.is_none() => naming::path(cfg, self.dir, &self.name, &[])
+				.ok()
+				.map(|p| self.output.join(p)),
+			_ => None,
+		}
 	}
 
-	Ok(())
+	fn scope<S, R>(&self, func: S) -> R
+	where
+		S: FnOnce() -> R,
+	{
+		logging::scope("input", self.dir, || {
+			logging::scope("item", &self.name, func)
+		})
+	}
 }
 
-fn process_dir(cfg: &JsonValue, dir: &Path, working: &Path, output: &Path) -> Result<(), ()> {
-	let files = std::fs::read_dir(dir);
-	if let Err(err) = files {
-		logging::error!("Failed to list files in directory: {}", err);
-		return Err(());
+pub fn process(
+	cfgs: &BTreeMap<String, Config>,
+	input: &Path,
+	working: &Path,
+	output: &Path,
+	dryrun: bool,
+	keepgoing: bool,
+	scheduler: &Scheduler,
+) -> Result<Vec<Report>> {
+	let dirs = dirs(input)?;
+
+	let mut items = Vec::<Item>::new();
+	let mut reports = Vec::<Report>::new();
+
+	for name in &dirs {
+		let data = match config::assign(cfgs, name) {
+			Ok(Some(data)) => data,
+			Ok(None) => {
+				logging::warn!(
+					"No configuration matches input directory {}, skipping",
+					name
+				);
+				continue;
+			}
+			Err(err) => {
+				logging::error!("{}", err);
+
+				reports.push(Report {
+					dir: name.clone(),
+					item: String::new(),
+					status: Status::Failed(err),
+				});
+
+				continue;
+			}
+		};
+
+		if data.name != *name {
+			logging::debug!("Input directory {} uses configuration {}", name, data.name);
+		}
+
+		// Working directories are named after the input, a configuration can have many
+		let path = input.join(name);
+		let wdir = working.join(name);
+
+		// A dry run should not leave any traces
+		if !dryrun {
+			std::fs::create_dir_all(&wdir).map_err(|e| Error::io(&wdir, e))?;
+		}
+
+		let found = find_items(name, data, &path, &wdir, output, dryrun)?;
+
+		items.extend(found);
+	}
+
+	let failed = AtomicBool::new(false);
+	let claims = Claims::default();
+
+	let statuses = std::thread::scope(|s| {
+		let mut handles = Vec::new();
+
+		for item in &items {
+			let permit = scheduler.item();
+
+			// Unless requested, don't start new items once one of them failed
+			if !keepgoing && failed.load(Ordering::SeqCst) {
+				break;
+			}
+
+			let failed = &failed;
+			let claims = &claims;
+
+			handles.push(logging::spawn(s, move || {
+				let _permit = permit;
+
+				let status = item.scope(|| {
+					let status = item.config().and_then(|cfg| {
+						if dryrun {
+							plan_item(&cfg, item, claims)
+						} else {
+							process_item(&cfg, item, claims, scheduler)
+						}
+					});
+
+					// Errors are only reported once, with the item they belong to
+					if let Err(err) = &status {
+						logging::error!("{}", err);
+					}
+
+					status
+				});
+
+				if status.is_err() {
+					failed.store(true, Ordering::SeqCst);
+				}
+
+				status
+			}));
+		}
+
+		handles
+			.into_iter()
+			.map(|h| h.join().unwrap_or_else(|p| Err(Error::panic(p))))
+			.collect::<Vec<_>>()
+	});
+
+	let mut statuses = statuses.into_iter();
+
+	for item in &items {
+		let status = match statuses.next() {
+			Some(Ok(status)) => status,
+			Some(Err(err)) => Status::Failed(err),
+			None => Status::Aborted,
+		};
+
+		reports.push(Report {
+			dir: String::from(item.dir),
+			item: item.name.clone(),
+			status,
+		});
 	}
 
-	let files = files.unwrap();
+	Ok(reports)
+}
+
+fn list(dir: &Path) -> Result<Vec<PathBuf>> {
+	let files = std::fs::read_dir(dir).map_err(|e| Error::io(dir, e))?;
 	let mut files = files
 		.map(|res| res.map(|e| e.path()))
-		.collect::<Result<Vec<_>, io::Error>>()
-		.unwrap();
+		.collect::<std::result::Result<Vec<_>, io::Error>>()
+		.map_err(|e| Error::io(dir, e))?;
 
 	files.sort();
+	Ok(files)
+}
 
-	for path in files {
-		let ext = path.extension().unwrap_or_default();
+// Names of the input folders, each of them is processed by one configuration
+pub fn dirs(input: &Path) -> Result<Vec<String>> {
+	let dirs = list(input)?
+		.into_iter()
+		.filter(|p| p.is_dir())
+		.filter_map(|p| p.file_name().and_then(|n| n.to_str()).map(String::from))
+		.collect();
 
-		// We only care about mkv files and directories
-		if !(path.is_dir() || path.is_file() && ext == "mkv") {
+	Ok(dirs)
+}
+
+// Names and paths of the items in an input folder, names are relative to it like Season 1/ep01
+pub fn items(dir: &Path, layout: &Layout) -> Result<Vec<(String, PathBuf)>> {
+	let mut paths = Vec::<PathBuf>::new();
+	walk(dir, layout, 0, &mut paths)?;
+
+	let mut items = Vec::<(String, PathBuf)>::new();
+
+	for path in paths {
+		let rel = path.strip_prefix(dir).unwrap();
+		let stem = rel.file_stem().unwrap_or_default().to_str();
+		let parent = rel.parent().unwrap_or(Path::new("")).to_str();
+
+		let name = match (parent, stem) {
+			(Some(""), Some(stem)) => String::from(stem),
+			(Some(parent), Some(stem)) => format!("{parent}/{stem}"),
+			_ => continue,
+		};
+
+		// The working directory and output are named after the item, e.g. ep1.mkv and ep1.mp4 clash
+		if items.iter().any(|i| i.0 == name) {
+			logging::warn!(
+				"Skipping {}, there is another item named {}",
+				path.to_str().unwrap(),
+				name
+			);
 			continue;
 		}
 
-		let name = path.file_stem().unwrap_or_default().to_str();
-		if name.is_none() {
-			continue;
+		items.push((name, path));
+	}
+
+	Ok(items)
+}
+
+fn walk(dir: &Path, layout: &Layout, depth: usize, found: &mut Vec<PathBuf>) -> Result<()> {
+	for path in list(dir)? {
+		let media = mkv::is_container(&path);
+
+		// Discs are never searched for more items
+		let folder = path.is_dir() && !disc::is_disc(&path);
+
+		match layout {
+			// We only care about media files and directories
+			Layout::Flat => {
+				if media || path.is_dir() {
+					found.push(path);
+				}
+			}
+			Layout::Files => {
+				if media || disc::is_disc(&path) {
+					found.push(path);
+				} else if folder {
+					walk(&path, layout, depth + 1, found)?;
+				}
+			}
+			Layout::Directories => {
+				if disc::is_disc(&path)
+					|| folder && list(&path)?.iter().any(|p| mkv::is_container(p))
+				{
+					found.push(path);
+				} else if folder {
+					walk(&path, layout, depth + 1, found)?;
+				}
+			}
+			Layout::Pattern(globs) => {
+				let name = path
+					.file_name()
+					.unwrap_or_default()
+					.to_str()
+					.unwrap_or_default();
+				if !globs[depth].is_match(name) {
+					continue;
+				}
+
+				if depth + 1 == globs.len() {
+					if media || path.is_dir() {
+						found.push(path);
+					}
+				} else if folder {
+					walk(&path, layout, depth + 1, found)?;
+				}
+			}
 		}
+	}
 
-		let name = String::from(name.unwrap());
-		let wdir = working.join(&name);
-		let ofile = output.join(&name).with_extension("mkv");
+	Ok(())
+}
 
-		let err = std::fs::create_dir_all(&wdir);
-		if let Err(err) = err {
-			let name = wdir.to_str().unwrap();
+fn find_items<'a>(
+	input: &'a str,
+	cfg: &'a Config,
+	dir: &Path,
+	working: &Path,
+	output: &Path,
+	dryrun: bool,
+) -> Result<Vec<Item<'a>>> {
+	let mut found = Vec::<Item>::new();
+
+	for (name, path) in items(dir, &cfg.layout)? {
+		let wdir = working.join(&name);
 
-			logging::error!("Failed to create directory {}: {}", name, err);
-			return Err(());
+		// Folders of the input are mirrored in the working directory
+		if !dryrun {
+			std::fs::create_dir_all(&wdir).map_err(|e| Error::io(&wdir, e))?;
 		}
 
-		logging::scope("item", &name, || process_item(cfg, &path, &wdir, &ofile))?;
+		found.push(Item {
+			dir: input,
+			cfg,
+			name,
+			path,
+			working: wdir,
+			output: output.to_path_buf(),
+		});
 	}
 
-	Ok(())
+	Ok(found)
 }
 
-fn process_item(cfg: &JsonValue, path: &Path, working: &Path, output: &Path) -> Result<(), ()> {
-	let mut files = Vec::<PathBuf>::new();
+fn process_item(
+	cfg: &Config,
+	item: &Item,
+	claims: &Claims,
+	scheduler: &Scheduler,
+) -> Result<Status> {
+	let working = item.working.as_path();
+
+	let early = item
+		.early_output(cfg, claims)
+		.map_err(|e| e.context("output"))?;
+
+	let files = item_files(&item.path, cfg).map_err(|e| e.context("title"))?;
+	let fingerprint = fingerprint(cfg, &files);
+
+	let previous = item.previous(cfg);
+	if previous
+		.as_ref()
+		.is_some_and(|p| up_to_date(working, p, &fingerprint))
+	{
+		return Ok(Status::Exists);
+	}
 
-	if output.exists() {
-		return Ok(());
+	if let Some(output) = &early {
+		overwrites(output, previous.as_ref()).map_err(|e| e.context("output"))?;
 	}
 
-	// Build a list of all mkv files related to the current item
-	if path.is_file() {
-		files.push(path.to_path_buf());
-	} else {
-		for entry in std::fs::read_dir(path).expect("read_dir failed") {
-			let p2 = entry.unwrap().path();
-			let ext = p2.extension().unwrap_or_default();
+	let streams = {
+		let _job = scheduler.job(None);
 
-			if !p2.is_file() || ext != "mkv" {
-				continue;
-			}
+		// Normalize the metadata of the input files
+		let files = mkv::normalize(&files, working).map_err(|e| e.context("normalize"))?;
+
+		// Parts of one timeline are probed and processed as one file
+		let files = match cfg.join {
+			Join::Concat(_) => vec![mkv::join(&files, working).map_err(|e| e.context("join"))?],
+			Join::Parallel => files,
+		};
+
+		// Probe all streams of the input files
+		mkv::streams(&files, Some(working)).map_err(|e| e.context("probe"))?
+	};
+
+	// Select the input streams we care about
+	let streams = select::find(cfg, &streams).map_err(|e| e.context("select"))?;
+
+	// Run processing filters, independent streams can run at the same time
+	let processed = std::thread::scope(|s| {
+		let mut handles = Vec::new();
 
-			files.push(p2);
+		for entry in streams {
+			let name = entry.0;
+			let cfg = entry.1;
+			let stream = entry.2;
+
+			handles.push(logging::spawn(s, move || {
+				let _job = scheduler.job(Some(stream.streamtype));
+				let wdir = stream.path.parent().unwrap();
+
+				let scope = format!("{} ({})", name, stream.id);
+
+				logging::scope("stream", &scope, || {
+					filters::run(&cfg, &name, &stream, wdir)
+				})
+				.map_err(|e| e.context(format!("stream {scope}")))
+			}));
 		}
+
+		handles
+			.into_iter()
+			.map(|h| h.join().unwrap_or_else(|p| Err(Error::panic(p))))
+			.collect::<Result<Vec<_>>>()
+	})?;
+
+	let output = match early {
+		Some(output) => output,
+		None => item
+			.output(cfg, &processed, claims)
+			.map_err(|e| e.context("output"))?,
+	};
+
+	overwrites(&output, previous.as_ref()).map_err(|e| e.context("output"))?;
+
+	let odir = output.parent().unwrap();
+	std::fs::create_dir_all(odir).map_err(|e| Error::io(odir, e).context("output"))?;
+
+	// Combine the processed streams into a new mkv
+	let _job = scheduler.job(None);
+	mkv::write(&processed, &output).map_err(|e| e.context("mux"))?;
+
+	// A changed template renames the output, the old one is replaced
+	if let Some(previous) = previous.filter(|p| *p != output && p.exists()) {
+		logging::info!(
+			"Removing {}, the output is now {}",
+			previous.to_str().unwrap(),
+			output.to_str().unwrap()
+		);
+
+		std::fs::remove_file(&previous).map_err(|e| Error::io(&previous, e).context("mux"))?;
+	}
+
+	let record = record(working);
+	let data = format!("{}\n{}", fingerprint, output.to_str().unwrap());
+	std::fs::write(&record, data).map_err(|e| Error::io(&record, e).context("mux"))?;
+
+	Ok(Status::Processed)
+}
+
+// Fails if the output exists and was not written by the item before
+fn overwrites(output: &Path, previous: Option<&PathBuf>) -> Result<()> {
+	if output.exists() && previous.is_none_or(|p| p != output) {
+		return Err(Error::Collision {
+			path: output.to_path_buf(),
+			owner: None,
+		});
 	}
 
-	// Normalize the metadata of the input files
-	let files = mkv::normalize(&files, working)?;
+	Ok(())
+}
 
-	// Probe all streams of the input files
-	let streams = mkv::streams(&files)?;
+fn plan_item(cfg: &Config, item: &Item, claims: &Claims) -> Result<Status> {
+	let working = item.working.as_path();
 
-	// Select the input streams we care about
-	let streams = select::find(cfg, &streams).unwrap();
+	let early = item
+		.early_output(cfg, claims)
+		.map_err(|e| e.context("output"))?;
+
+	let inputs = item_files(&item.path, cfg).map_err(|e| e.context("title"))?;
+	let fingerprint = fingerprint(cfg, &inputs);
+
+	if let Some(previous) = item.previous(cfg) {
+		if up_to_date(working, &previous, &fingerprint) {
+			logging::info!("Output {} exists, skipping", previous.to_str().unwrap());
+			return Ok(Status::Exists);
+		}
+	}
+
+	let files = sources(cfg, &inputs, working)?;
+	let parts = mkv::normalized(&inputs, working);
+	let norms = match cfg.join {
+		Join::Concat(_) => vec![mkv::joined(&parts, working)],
+		Join::Parallel => parts.clone(),
+	};
+
+	if matches!(cfg.join, Join::Concat(_)) && !mkv::is_current(&norms[0], &parts) {
+		logging::info!(
+			"The {} parts are joined first, showing the streams of the first part",
+			inputs.len()
+		);
+	}
+
+	let streams = mkv::streams(&files, None).map_err(|e| e.context("probe"))?;
+	let streams = select::find(cfg, &streams).map_err(|e| e.context("select"))?;
 
-	// Store the processed streams
 	let mut processed = Vec::<mkv::Stream>::new();
+	let mut pending = false;
 
-	// Run processing filters
 	for entry in streams {
 		let name = entry.0;
 		let cfg = entry.1;
 		let stream = entry.2;
-		let wdir = stream.path.parent().unwrap();
 
-		let stream = logging::scope(
-			"stream",
-			format!("{} ({})", name, stream.id).as_str(),
-			|| filters::run(&cfg, &stream, wdir),
-		)?;
-		processed.push(stream);
+		// The stages run next to the normalized file
+		let i = files.iter().position(|f| *f == stream.path).unwrap();
+		let wdir = norms[i].parent().unwrap();
+
+		logging::info!(
+			"Stream {}: {} #{} ({}) from {}",
+			name,
+			stream.id,
+			stream.index,
+			stream.streamtype,
+			stream.path.to_str().unwrap()
+		);
+
+		let stages = filters::plan(&cfg, &stream, wdir).map_err(|e| {
+			e.context("filter configuration")
+				.context(format!("stream {name}"))
+		})?;
+
+		let fingerprints = filters::fingerprints(&stream, &stages);
+		let (done, current) = filters::resume(&name, &stream, wdir, &fingerprints);
+
+		for (i, stage) in stages.iter().enumerate() {
+			let state = if i < done { "done" } else { "pending" };
+			logging::info!("  Stage{} {}: {}", i + 1, state, stage.name);
+		}
+
+		if done < stages.len() {
+			pending = true;
+		}
+
+		processed.push(current);
 	}
 
-	// Combine the processed streams into a new mkv
-	mkv::write(&processed, output)
+	if pending {
+		logging::info!("Some stages did not run yet, the commands below use their inputs");
+	}
+
+	let output = match early {
+		Some(output) => output,
+		None => item
+			.output(cfg, &processed, claims)
+			.map_err(|e| e.context("output"))?,
+	};
+
+	for (tool, args) in mkv::commands(&processed, &output) {
+		logging::info!("{}", tool.cmdline(&args));
+	}
+
+	Ok(Status::Processed)
+}
+
+// Changes when the configuration, a filter template, an input file or a tool changes
+fn fingerprint(cfg: &Config, files: &[PathBuf]) -> String {
+	let mut parts = vec![cfg.settings()];
+	let mut required = mkv::TOOLS.to_vec();
+
+	for entry in &cfg.streams {
+		for option in &entry.options {
+			tools::add(&mut required, &filters::tools(option));
+
+			for filter in &option.filters {
+				if let Filter::VapourSynth { filter } | Filter::AviSynth { filter } = filter {
+					let template = std::fs::read_to_string(filter);
+					parts.push(template.unwrap_or_default());
+				}
+			}
+		}
+	}
+
+	for file in files {
+		let meta = std::fs::metadata(file).ok();
+		let size = meta.as_ref().map(|m| m.len()).unwrap_or_default();
+		let modified = meta
+			.and_then(|m| m.modified().ok())
+			.and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+			.unwrap_or_default();
+
+		parts.push(format!(
+			"{} {} {}",
+			file.to_str().unwrap(),
+			size,
+			modified.as_secs()
+		));
+	}
+
+	parts.extend(required.iter().map(tools::version));
+
+	let parts = parts.iter().map(String::as_str).collect::<Vec<_>>();
+	utils::fingerprint(&parts)
+}
+
+// The fingerprint of an item is kept next to its working directory, so cleaning it up keeps it
+fn record(working: &Path) -> PathBuf {
+	let name = working.file_name().unwrap().to_str().unwrap();
+	working.with_file_name(format!("{name}.fingerprint"))
+}
+
+// The fingerprint of the last run and the output it wrote, older versions only kept the fingerprint
+fn recorded(working: &Path) -> Option<(String, Option<PathBuf>)> {
+	let data = std::fs::read_to_string(record(working)).ok()?;
+	let mut lines = data.lines();
+
+	let fingerprint = String::from(lines.next().unwrap_or_default());
+	Some((fingerprint, lines.next().map(PathBuf::from)))
+}
+
+// Outputs without a recorded fingerprint were written by an older version and are kept
+fn up_to_date(working: &Path, output: &Path, fingerprint: &str) -> bool {
+	if !output.exists() {
+		return false;
+	}
+
+	if recorded(working).is_some_and(|r| r.0 != fingerprint) {
+		logging::info!("Configuration, inputs or tools changed, processing again");
+		return false;
+	}
+
+	true
+}
+
+pub fn sources(cfg: &Config, files: &Vec<PathBuf>, working: &Path) -> Result<Vec<PathBuf>> {
+	let norms = mkv::normalized(files, working);
+
+	let concat = matches!(cfg.join, Join::Concat(_));
+
+	let joined = mkv::joined(&norms, working);
+	if concat && mkv::is_current(&joined, &norms) {
+		return Ok(vec![joined]);
+	}
+
+	// Use the normalized files if they exist, otherwise use the inputs directly
+	let mut sources = files
+		.iter()
+		.zip(&norms)
+		.map(|(file, norm)| {
+			if mkv::is_current(norm, &disc::parts(file)) {
+				Ok(norm.clone())
+			} else {
+				disc::preview(file)
+			}
+		})
+		.collect::<Result<Vec<_>>>()?;
+
+	// All parts have the same streams, the first one stands in for the joined file
+	if concat {
+		sources.truncate(1);
+	}
+
+	Ok(sources)
+}
+
+pub fn item_files(path: &Path, cfg: &Config) -> Result<Vec<PathBuf>> {
+	let mut files = Vec::<PathBuf>::new();
+
+	// Build a list of all media files related to the current item
+	if disc::is_disc(path) {
+		files.push(disc::title(path, &cfg.title)?);
+	} else if path.is_file() {
+		files.push(path.to_path_buf());
+	} else {
+		let entries = list(path)?;
+		files.extend(entries.into_iter().filter(|p| mkv::is_container(p)));
+	}
+
+	match &cfg.join {
+		Join::Concat(globs) if !globs.is_empty() => order(cfg, files, globs),
+		_ => Ok(files),
+	}
+}
+
+// Parts are ordered by the first glob they match, parts matching the same glob by name
+fn order(cfg: &Config, files: Vec<PathBuf>, globs: &[Regex]) -> Result<Vec<PathBuf>> {
+	let mut parts = Vec::<(usize, PathBuf)>::new();
+
+	for file in files {
+		let name = file
+			.file_name()
+			.unwrap_or_default()
+			.to_str()
+			.unwrap_or_default();
+
+		match globs.iter().position(|g| g.is_match(name)) {
+			Some(i) => parts.push((i, file)),
+			None => {
+				let reason = format!("part {name} matches none of the globs");
+				return Err(Error::config(cfg.file(), "join.concat", reason));
+			}
+		}
+	}
+
+	parts.sort();
+	Ok(parts.into_iter().map(|p| p.1).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use tempdir::TempDir;
+
+	use super::*;
+
+	fn input(files: &[&str]) -> TempDir {
+		let dir = TempDir::new("videoproc").unwrap();
+
+		for file in files {
+			let path = dir.path().join(file);
+
+			std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+			std::fs::write(path, "").unwrap();
+		}
+
+		dir
+	}
+
+	fn names(dir: &TempDir, layout: &Layout) -> Vec<String> {
+		let items = items(dir.path(), layout).unwrap();
+		items.into_iter().map(|(name, _)| name).collect()
+	}
+
+	#[test]
+	fn flat() {
+		let dir = input(&["ep01.mkv", "ep02.mp4", "notes.txt", "Extras/clip.mkv"]);
+		assert_eq!(names(&dir, &Layout::Flat), ["Extras", "ep01", "ep02"]);
+
+		// Items with the same name would share their working directory
+		let dir = input(&["ep01.mkv", "ep01.mp4"]);
+		assert_eq!(names(&dir, &Layout::Flat), ["ep01"]);
+	}
+
+	#[test]
+	fn pattern() {
+		let dir = input(&[
+			"Season 1/ep01.mkv",
+			"Season 1/Extras/clip.mkv",
+			"Season 2/ep01.mkv",
+			"Extras/clip.mkv",
+		]);
+
+		let globs = ["Season *", "*"]
+			.iter()
+			.map(|g| fnmatch_regex::glob_to_regex(g).unwrap())
+			.collect();
+
+		let names = names(&dir, &Layout::Pattern(globs));
+		assert_eq!(names, ["Season 1/Extras", "Season 1/ep01", "Season 2/ep01"]);
+	}
 }
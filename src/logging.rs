@@ -1,5 +1,8 @@
+use std::thread::{Scope, ScopedJoinHandle};
+
 use slog::{self, Drain};
 
+#[allow(unused_imports)]
 pub use slog_scope::{crit, debug, error, info, trace, warn};
 
 pub fn init() -> slog_scope::GlobalLoggerGuard {
@@ -20,3 +23,17 @@ where
 
 	slog_scope::scope(&logger, func)
 }
+
+pub fn spawn<'scope, 'env, S, R>(
+	scope: &'scope Scope<'scope, 'env>,
+	func: S,
+) -> ScopedJoinHandle<'scope, R>
+where
+	S: FnOnce() -> R + Send + 'scope,
+	R: Send + 'scope,
+{
+	// Keep the logging context of the current thread
+	let logger = slog_scope::logger();
+
+	scope.spawn(move || slog_scope::scope(&logger, func))
+}
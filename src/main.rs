@@ -1,14 +1,22 @@
-use std::{path::PathBuf, process::Command};
+use std::path::PathBuf;
 
-use argparse::{ArgumentParser, Store};
-use execute::Execute;
+use argparse::{ArgumentParser, Collect, List, Store};
 
+mod codec;
+mod commands;
 mod config;
+mod disc;
+mod ebml;
+mod error;
 mod filters;
 mod input;
 mod logging;
 mod mkv;
+mod naming;
+mod scheduler;
 mod select;
+mod timestamp;
+mod tools;
 mod utils;
 
 fn main() {
@@ -18,148 +26,59 @@ fn main() {
 fn run() -> i32 {
 	let _guard = logging::init();
 
-	let mut config = PathBuf::from("config");
-	let mut input = PathBuf::from("input");
-	let mut working = PathBuf::from("working");
-	let mut output = PathBuf::from("output");
+	let mut options = commands::Options {
+		config: PathBuf::from("config"),
+		input: PathBuf::from("input"),
+		working: PathBuf::from("working"),
+		output: PathBuf::from("output"),
+	};
+
+	let mut paths = Vec::<String>::new();
+	let mut toolargs = Vec::<String>::new();
+
+	let mut command = String::from("process");
+	let mut args = Vec::<String>::new();
 
 	{
+		let help = format!("Command to run ({})", commands::COMMANDS);
+
 		let mut parser = ArgumentParser::new();
 		parser
-			.refer(&mut config)
+			.refer(&mut options.config)
 			.add_option(&["--config"], Store, "Config directory");
 		parser
-			.refer(&mut input)
+			.refer(&mut options.input)
 			.add_option(&["--input"], Store, "Input directory");
 		parser
-			.refer(&mut working)
+			.refer(&mut options.working)
 			.add_option(&["--working"], Store, "Working directory");
 		parser
-			.refer(&mut output)
+			.refer(&mut options.output)
 			.add_option(&["--output"], Store, "Output directory");
+		parser.refer(&mut paths).add_option(
+			&["--tool"],
+			Collect,
+			"Binary of a tool, e.g. ffmpeg=/opt/ffmpeg/bin/ffmpeg",
+		);
+		parser.refer(&mut toolargs).add_option(
+			&["--tool-args"],
+			Collect,
+			"Default arguments of a tool, e.g. \"ffmpeg=-hide_banner -nostdin\"",
+		);
+		parser
+			.refer(&mut command)
+			.add_argument("command", Store, &help);
+		parser
+			.refer(&mut args)
+			.add_argument("arguments", List, "Arguments for the command");
+		parser.stop_on_first_argument(true);
 		parser.parse_args_or_exit();
 	}
 
-	/*
-	 * Check if directories exist
-	 */
-
-	if !config.exists() || !config.is_dir() {
-		logging::error!("{} is not a directory!", config.to_str().unwrap());
-		return 1;
-	}
-
-	if !input.exists() || !input.is_dir() {
-		logging::error!("{} is not a directory!", input.to_str().unwrap());
-		return 1;
-	}
-
-	if working.exists() && !working.is_dir() {
-		logging::error!("{} is not a directory!", working.to_str().unwrap());
-		return 1;
-	}
-
-	if output.exists() && !output.is_dir() {
-		logging::error!("{} is not a directory!", output.to_str().unwrap());
-		return 1;
-	}
-
-	/*
-	 * Create working and output directories
-	 */
-
-	let err = std::fs::create_dir_all(&working);
-	if let Err(err) = err {
-		logging::error!("Failed to create working directory: {}", err);
-		return 1;
-	}
-
-	let err = std::fs::create_dir_all(&output);
-	if let Err(err) = err {
-		logging::error!("Failed to create output directory: {}", err);
-		return 1;
-	}
-
-	config = config.canonicalize().unwrap();
-	input = input.canonicalize().unwrap();
-	working = working.canonicalize().unwrap();
-	output = output.canonicalize().unwrap();
-
-	/*
-	 * Test commandline programs
-	 */
-
-	let cmd = Command::new("ffmpeg")
-		.arg("-version")
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run ffmpeg: {}", err);
-		return 1;
-	}
-
-	let cmd = Command::new("ffprobe")
-		.arg("-version")
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run ffprobe: {}", err);
-		return 1;
-	}
-
-	let cmd = Command::new("vspipe")
-		.arg("--version")
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run vspipe: {}", err);
-		return 1;
-	}
-
-	let cmd = Command::new("mediainfo")
-		.arg("--version")
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run mediainfo: {}", err);
-		return 1;
-	}
-
-	let cmd = Command::new("mkvmerge")
-		.arg("--version")
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run mkvmerge: {}", err);
-		return 1;
-	}
-
-	let cmd = Command::new("mkvextract")
-		.arg("--version")
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run mkvextract: {}", err);
-		return 1;
-	}
-
-	let cmd = Command::new("d2vwitch")
-		.arg("--version")
-		.execute_check_exit_status_code(0);
-
-	if let Err(err) = cmd {
-		logging::error!("Failed to run d2vwitch: {}", err);
+	if let Err(err) = tools::init(&options.config, &paths, &toolargs) {
+		logging::error!("{}", err);
 		return 1;
 	}
 
-	let cmd = Command::new("avs2yuv").execute_check_exit_status_code(2);
-	if let Err(err) = cmd {
-		logging::error!("Failed to run avs2yuv: {}", err);
-		return 1;
-	}
-
-	// Process inputs
-	let _ = input::process(&config, &input, &working, &output);
-
-	0
+	commands::run(&command, &options, args)
 }
@@ -1,29 +1,93 @@
-use execute::Execute;
-use json::object;
+use json::{object, JsonValue};
 
 use crate::{
+	codec::Codec,
+	disc, ebml,
+	error::{Error, Result},
 	logging,
+	timestamp::Timestamp,
+	tools::{self, Tool},
 	utils::{self, framerate, StrVec},
 };
 use std::{
-	io,
+	fmt, io,
 	path::{Path, PathBuf},
-	process::Command,
+	time::UNIX_EPOCH,
 };
 
+// Normalizing, probing and writing
+pub const TOOLS: &[Tool] = &[
+	tools::MKVMERGE,
+	tools::FFPROBE,
+	tools::MEDIAINFO,
+	tools::FFMPEG,
+];
+
+// Containers that are accepted as input, they are remuxed into MKV before anything else
+pub const CONTAINERS: &[&str] = &["mkv", "mp4", "m2ts", "ts", "mov", "webm", "vob", "avi"];
+
+// Version of the stream JSON in commits and probe caches, files of other versions are redone
+//
+// Files without a version are version 1, which stored timestamps as seconds.
+pub const VERSION: u32 = 2;
+
+// Stream types reported by ffprobe, and chapters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamType {
+	Video,
+	Audio,
+	Subtitle,
+	Attachment,
+	Data,
+	Chapters,
+}
+
+impl StreamType {
+	pub const ALL: &'static [Self] = &[
+		Self::Video,
+		Self::Audio,
+		Self::Subtitle,
+		Self::Attachment,
+		Self::Data,
+		Self::Chapters,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Video => "video",
+			Self::Audio => "audio",
+			Self::Subtitle => "subtitle",
+			Self::Attachment => "attachment",
+			Self::Data => "data",
+			Self::Chapters => "chapters",
+		}
+	}
+
+	pub fn parse(name: &str) -> Option<Self> {
+		Self::ALL.iter().copied().find(|t| t.name() == name)
+	}
+}
+
+impl fmt::Display for StreamType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+
 #[derive(Clone)]
 pub struct Stream {
 	pub path: PathBuf,
 	pub index: i32,
-	pub streamtype: String,
+	pub streamtype: StreamType,
 	pub id: String,
 	pub language: Option<String>,
-	pub offset: f32,
-	pub duration: f32,
-	pub codec: Option<String>,
+	pub offset: Timestamp,
+	pub duration: Timestamp,
+	pub codec: Option<Codec>,
 
 	pub aspect: Option<String>,
 	pub framerate: Option<(u32, u32)>,
+	pub height: Option<u32>,
 
 	pub samplerate: Option<u32>,
 	pub channels: Option<u32>,
@@ -32,40 +96,35 @@ pub struct Stream {
 }
 
 impl Stream {
-	pub fn load(path: &Path) -> Result<Self, ()> {
-		let data = std::fs::read_to_string(path);
-		if let Err(err) = data {
-			let name = path.to_str().unwrap();
-
-			logging::error!("Failed to read {}: {}", name, err);
-			return Err(());
-		}
-
-		let data = json::parse(&data.unwrap());
-		if let Err(err) = data {
-			let name = path.to_str().unwrap();
-
-			logging::error!("Failed to parse {}: {}", name, err);
-			return Err(());
-		}
-
-		let mut data = data.unwrap();
-
+	// The path is only used for error messages
+	pub fn from_json(path: &Path, mut data: JsonValue) -> Result<Self> {
 		let stream = Self {
 			path: PathBuf::from(data["path"].to_string()),
-			index: data["index"].as_i32().unwrap(),
-			streamtype: data["streamtype"].to_string(),
+			index: data["index"]
+				.as_i32()
+				.ok_or_else(|| Error::parse(path, "missing index"))?,
+			streamtype: data["streamtype"]
+				.as_str()
+				.and_then(StreamType::parse)
+				.ok_or_else(|| Error::parse(path, "missing stream type"))?,
 			id: data["id"].to_string(),
 			language: data["language"].take_string(),
-			codec: data["codec"].take_string(),
-			offset: data["offset"].as_f32().unwrap(),
-			duration: data["duration"].as_f32().unwrap(),
+			codec: data["codec"].as_str().map(Codec::parse),
+			offset: data["offset"]
+				.as_str()
+				.and_then(Timestamp::parse)
+				.ok_or_else(|| Error::parse(path, "missing offset"))?,
+			duration: data["duration"]
+				.as_str()
+				.and_then(Timestamp::parse)
+				.ok_or_else(|| Error::parse(path, "missing duration"))?,
 			aspect: data["aspect"].take_string(),
 			framerate: if data["framerate"].is_null() {
 				None
 			} else {
 				Some(framerate(&data["framerate"].to_string()))
 			},
+			height: data["height"].as_u32(),
 			samplerate: data["samplerate"].as_u32(),
 			channels: data["channels"].as_u32(),
 			dialnorm: data["dialnorm"].as_i32(),
@@ -75,49 +134,56 @@ impl Stream {
 		Ok(stream)
 	}
 
-	pub fn save(&self, path: &Path) -> io::Result<()> {
-		let framerate = if self.framerate.is_some() {
-			let f = self.framerate.unwrap();
-			Some(format!("{}/{}", f.0, f.1))
-		} else {
-			None
-		};
+	pub fn to_json(&self) -> JsonValue {
+		let framerate = self.framerate.map(|f| format!("{}/{}", f.0, f.1));
 
-		let obj = object! {
+		object! {
 			path: self.path.to_str().unwrap(),
 			index: self.index,
-			streamtype: self.streamtype.as_str(),
+			streamtype: self.streamtype.name(),
 			id: self.id.as_str(),
 			language: self.language.as_deref(),
-			codec: self.codec.as_deref(),
-			offset: self.offset,
-			duration: self.duration,
+			codec: self.codec.as_ref().map(Codec::name),
+			offset: self.offset.to_string(),
+			duration: self.duration.to_string(),
 			aspect: self.aspect.as_deref(),
 			framerate: framerate,
+			height: self.height,
 			samplerate: self.samplerate,
 			channels: self.channels,
 			dialnorm: self.dialnorm,
 			dsurmode: self.dsurmode,
-		};
-
-		let str = json::stringify_pretty(obj, 4);
-		std::fs::write(path, str)
+		}
 	}
 
-	pub fn cleanup(&self) -> io::Result<()> {
+	pub fn cleanup(&self) -> Result<()> {
 		if self.path.exists() {
-			return std::fs::remove_file(&self.path);
+			return std::fs::remove_file(&self.path).map_err(|e| Error::io(&self.path, e));
 		}
 
 		Ok(())
 	}
 }
 
-pub fn normalize(files: &Vec<PathBuf>, working: &Path) -> Result<Vec<PathBuf>, ()> {
+pub fn normalize(files: &Vec<PathBuf>, working: &Path) -> Result<Vec<PathBuf>> {
 	let mut new = Vec::<PathBuf>::new();
 
 	logging::info!("Normalizing metadata");
 
+	for (file, output) in files.iter().zip(normalized(files, working)) {
+		let wdir = output.parent().unwrap();
+		std::fs::create_dir_all(wdir).map_err(|e| Error::io(wdir, e))?;
+
+		normalize_file(file, &output)?;
+		new.push(output);
+	}
+
+	Ok(new)
+}
+
+pub fn normalized(files: &Vec<PathBuf>, working: &Path) -> Vec<PathBuf> {
+	let mut new = Vec::<PathBuf>::new();
+
 	for file in files {
 		let stem = file.file_stem().unwrap_or_default();
 
@@ -127,339 +193,687 @@ pub fn normalize(files: &Vec<PathBuf>, working: &Path) -> Result<Vec<PathBuf>, (
 			working.to_path_buf()
 		};
 
-		let err = std::fs::create_dir_all(&wdir);
-		if let Err(err) = err {
-			let name = wdir.to_str().unwrap();
+		let name = wdir.file_name().unwrap_or_default();
 
-			logging::error!("Failed to create directory {}: {}", name, err);
-			return Err(());
+		// Another title of a disc must not reuse the file of the previous one
+		if disc::is_title(file) {
+			let name = format!(
+				"{}.{}.norm.mkv",
+				name.to_str().unwrap(),
+				stem.to_str().unwrap()
+			);
+			new.push(wdir.join(name));
+		} else {
+			new.push(wdir.join(name).with_extension("norm.mkv"));
 		}
-
-		new.push(normalize_file(file, &wdir)?);
 	}
 
-	Ok(new)
+	new
 }
 
-fn normalize_file(file: &Path, working: &Path) -> Result<PathBuf, ()> {
-	let name = working.file_name().unwrap_or_default();
-	let output = working.join(name).with_extension("norm.mkv");
+pub fn is_container(path: &Path) -> bool {
+	let ext = path
+		.extension()
+		.unwrap_or_default()
+		.to_str()
+		.unwrap_or_default();
+	path.is_file() && CONTAINERS.contains(&ext.to_lowercase().as_str())
+}
 
-	if output.exists() {
-		return Ok(output);
+fn normalize_file(file: &Path, output: &Path) -> Result<()> {
+	let parts = disc::parts(file);
+	if is_current(output, &parts) {
+		return Ok(());
 	}
 
-	// Normalize MKV metadata by remuxing the file with mkvmerge
+	// Normalize metadata by remuxing the file with mkvmerge, which reads the other containers too
+	//
+	// mkvmerge reads Blu-ray playlists itself, the parts of a DVD title are appended.
 	let temp = output.with_extension("temp.mkv");
-	let cmd = Command::new("mkvmerge")
-		.arg("-o")
-		.arg(&temp)
-		.arg(file)
-		.execute_check_exit_status_code(0);
+	let result = remux(&parts, &temp);
+
+	let ext = file.extension().unwrap_or_default();
+	if result.is_err() && !ext.eq_ignore_ascii_case("mkv") && !disc::is_title(file) {
+		// Some files only ffmpeg can read, e.g. AVIs or transport streams with broken timestamps
+		logging::warn!(
+			"mkvmerge failed to read {}, remuxing with ffmpeg first",
+			file.to_str().unwrap()
+		);
+
+		let copy = output.with_extension("ffmpeg.mkv");
+		tools::run(
+			tools::FFMPEG
+				.command()
+				.arg("-y")
+				.arg("-fflags")
+				.arg("+genpts")
+				.arg("-i")
+				.arg(file)
+				.arg("-map")
+				.arg("0")
+				.arg("-dn")
+				.arg("-c")
+				.arg("copy")
+				.arg(&copy),
+		)?;
+
+		remux(std::slice::from_ref(&copy), &temp)?;
+		std::fs::remove_file(&copy).map_err(|e| Error::io(&copy, e))?;
+	} else {
+		result?;
+	}
 
-	if let Err(err) = cmd {
-		let name = file.to_str().unwrap();
+	std::fs::rename(&temp, output).map_err(|e| Error::io(&temp, e))?;
+	record(output, &parts)
+}
 
-		logging::error!("Failed to normalize {}: {}", name, err);
-		return Err(());
+// Joins the normalized parts of an item into one file
+pub fn join(files: &[PathBuf], working: &Path) -> Result<PathBuf> {
+	let output = joined(files, working);
+	if is_current(&output, files) {
+		return Ok(output);
 	}
 
-	let err = std::fs::rename(&temp, &output);
-	if let Err(err) = err {
-		let name = temp.to_str().unwrap();
+	logging::info!("Joining {} parts", files.len());
 
-		logging::error!("Failed to rename {}: {}", name, err);
-		return Err(());
-	}
+	let temp = output.with_extension("temp.mkv");
+	remux(files, &temp)?;
+
+	std::fs::rename(&temp, &output).map_err(|e| Error::io(&temp, e))?;
+	record(&output, files)?;
 
 	Ok(output)
 }
 
-pub fn streams(files: &Vec<PathBuf>) -> Result<Vec<Stream>, ()> {
+// Normalized and joined files remember the size and time of the files they were made of,
+// so they are made again when one of those changes
+fn recorded(output: &Path) -> PathBuf {
+	output.with_extension("sources")
+}
+
+pub fn is_current(output: &Path, sources: &[PathBuf]) -> bool {
+	let keys = sources.iter().map(|s| key(s)).collect::<Option<Vec<_>>>();
+	let previous = std::fs::read_to_string(recorded(output)).ok();
+
+	output.exists() && keys.is_some_and(|k| previous == Some(k.join("\n")))
+}
+
+fn record(output: &Path, sources: &[PathBuf]) -> Result<()> {
+	let keys = sources.iter().filter_map(|s| key(s)).collect::<Vec<_>>();
+	let path = recorded(output);
+
+	std::fs::write(&path, keys.join("\n")).map_err(|e| Error::io(&path, e))
+}
+
+// Other parts or another order give another file, so an old one is never reused
+pub fn joined(files: &[PathBuf], working: &Path) -> PathBuf {
+	let names = files
+		.iter()
+		.map(|f| f.to_str().unwrap())
+		.collect::<Vec<_>>();
+
+	let name = working.file_name().unwrap_or_default().to_str().unwrap();
+	let hash = utils::fingerprint(&names);
+
+	working.join(format!("{name}.{}.joined.mkv", &hash[..8]))
+}
+
+fn remux(files: &[PathBuf], output: &Path) -> Result<()> {
+	let mut cmd = tools::MKVMERGE.command();
+	cmd.arg("-o").arg(output);
+
+	// Appended files continue where the previous one ends, including their chapters
+	for (i, part) in files.iter().enumerate() {
+		if i > 0 {
+			cmd.arg("+");
+		}
+
+		cmd.arg(part);
+	}
+
+	// Exit code 1 only means there were warnings, e.g. about the timestamps of MP4 files
+	tools::status(&mut cmd, &[0, 1])
+}
+
+// Probes all streams of the files, the results are kept in the cache directory if there is one
+pub fn streams(files: &Vec<PathBuf>, cache: Option<&Path>) -> Result<Vec<Stream>> {
 	let mut streams = Vec::<Stream>::new();
 
 	for file in files {
-		if !file.exists() {
-			let name = file.to_str().unwrap();
+		if let Some(cached) = cache.and_then(|c| cached(c, file)) {
+			streams.extend(cached);
+			continue;
+		}
 
-			logging::error!("Failed to probe {}", name);
-			return Err(());
+		let probed = probe(file)?;
+
+		if let Some(cache) = cache {
+			store(cache, file, &probed)?;
 		}
 
-		let cmd = Command::new("ffprobe")
-			.arg(file)
-			.arg("-of")
-			.arg("json")
-			.arg("-show_format")
-			.arg("-show_chapters")
-			.arg("-probesize")
-			.arg("10G")
-			.arg("-analyzeduration")
-			.arg("10G")
-			.output();
+		streams.extend(probed);
+	}
 
-		let cmd = utils::check_output(cmd);
-		if let Err(err) = cmd {
-			let name = file.to_str().unwrap();
+	Ok(streams)
+}
 
-			logging::error!("Failed to probe {}: {}", name, err);
-			return Err(());
-		}
+pub fn stream(file: &Path, index: u32) -> Result<Stream> {
+	probe(file)?
+		.into_iter()
+		.find(|s| s.index == index as i32)
+		.ok_or_else(|| Error::parse(file, format!("stream {index} not found")))
+}
 
-		let data = String::from_utf8(cmd.unwrap().stdout);
-		if let Err(err) = data {
-			let name = file.to_str().unwrap();
+fn probe(file: &Path) -> Result<Vec<Stream>> {
+	if !file.exists() {
+		let err = io::Error::from(io::ErrorKind::NotFound);
+		return Err(Error::io(file, err));
+	}
 
-			logging::error!("Failed to decode probe of {}: {}", name, err);
-			return Err(());
+	// Reading the Matroska headers is much faster than letting ffprobe analyze the whole file
+	if ebml::is_matroska(file) {
+		if let Some(streams) = matroska(file)? {
+			return Ok(streams);
 		}
+	}
 
-		let data = json::parse(&data.unwrap());
-		if let Err(err) = data {
-			let name = file.to_str().unwrap();
+	ffprobe(file)
+}
 
-			logging::error!("Failed to decode probe of {}: {}", name, err);
-			return Err(());
+// Reads the streams from the Matroska headers, None if they are not enough to describe them
+fn matroska(file: &Path) -> Result<Option<Vec<Stream>>> {
+	// ffprobe copes better with damaged files
+	let mkv = match ebml::read(file) {
+		Ok(mkv) => mkv,
+		Err(err) => {
+			logging::debug!("Reading the Matroska headers failed: {}", err);
+			return Ok(None);
 		}
+	};
 
-		let data = data.unwrap();
-		let count = data["format"]["nb_streams"].as_u32().unwrap_or_default();
+	let mut streams = Vec::<Stream>::new();
 
-		for i in 0..count {
-			streams.push(stream(file, i)?);
+	// Subtitles and other sparse streams start with the file like ffprobe reports them
+	let start = mkv
+		.tracks
+		.iter()
+		.filter(|t| t.kind == 1 || t.kind == 2)
+		.filter_map(|t| t.start)
+		.min()
+		.unwrap_or_default();
+
+	for (index, track) in mkv.tracks.iter().enumerate() {
+		match track_stream(file, index, track, start) {
+			Some(stream) => streams.push(stream),
+			None => return Ok(None),
 		}
+	}
 
-		if data["chapters"].is_empty() {
-			continue;
+	// Dialog normalization and the surround mode are only in the AC-3 bitstream
+	if streams
+		.iter()
+		.any(|s| matches!(s.codec, Some(Codec::Ac3 | Codec::Eac3)))
+	{
+		let info = tools::output(
+			tools::MEDIAINFO
+				.command()
+				.arg(file)
+				.arg("-F")
+				.arg("--Output=JSON"),
+		)?;
+
+		let info = json::parse(&info).map_err(|e| Error::parse(file, e))?;
+
+		for stream in streams
+			.iter_mut()
+			.filter(|s| s.streamtype == StreamType::Audio)
+		{
+			// Mediainfo gives the track number of Matroska tracks as their ID
+			let number = mkv.tracks[stream.index as usize].number.to_string();
+			let track = info["media"]["track"]
+				.members()
+				.find(|t| t["@type"] != "General" && t["ID"] == number.as_str());
+
+			let extra = match track {
+				Some(track) => &track["extra"],
+				None => continue,
+			};
+
+			stream.dialnorm = extra["dialnorm_Average"].to_string().parse::<i32>().ok();
+			stream.dsurmode = extra["dsurmod"].to_string().parse::<u32>().ok();
 		}
+	}
 
-		let chapters = Stream {
-			path: file.clone(),
-			index: -1,
-			streamtype: String::from("chapters"),
-			id: String::from("chapters"),
+	for attachment in &mkv.attachments {
+		let index = streams.len();
+
+		let codec = match attachment.mime.as_str() {
+			"font/ttf" | "application/x-truetype-font" | "application/x-font" => Some(Codec::Ttf),
+			"font/otf" | "application/vnd.ms-opentype" => Some(Codec::Otf),
+			_ => None,
+		};
+
+		streams.push(Stream {
+			path: file.to_path_buf(),
+			index: index as i32,
+			streamtype: StreamType::Attachment,
+			id: format!("attachment{index}"),
 			language: None,
-			codec: None,
-			offset: 0.0,
-			duration: 0.0,
+			codec,
+			offset: Timestamp::ZERO,
+			duration: Timestamp::ZERO,
 			aspect: None,
 			framerate: None,
+			height: None,
 			samplerate: None,
 			channels: None,
 			dialnorm: None,
 			dsurmode: None,
-		};
+		});
+	}
 
-		streams.push(chapters);
+	if mkv.chapters > 0 {
+		streams.push(chapters(file));
 	}
 
-	Ok(streams)
+	Ok(Some(streams))
 }
 
-pub fn stream(file: &Path, index: u32) -> Result<Stream, ()> {
-	if !file.exists() {
-		let name = file.to_str().unwrap();
-
-		logging::error!("Failed to probe {}", name);
-		return Err(());
-	}
+// A stream of a track, None if the headers don't have everything ffprobe would report
+fn track_stream(file: &Path, index: usize, track: &ebml::Track, start: i64) -> Option<Stream> {
+	let streamtype = match track.kind {
+		1 => StreamType::Video,
+		2 => StreamType::Audio,
+		17 => StreamType::Subtitle,
+		_ => return None,
+	};
+
+	let codec = codec(track)?;
+
+	let (framerate, aspect) = if streamtype == StreamType::Video {
+		let rate = track.frame.and_then(|f| rate(f, track.progressive))?;
+		(Some(rate), track.display.map(aspect))
+	} else {
+		(None, None)
+	};
+
+	// Only mkvmerge's statistics tags have the duration of each track
+	let duration = Timestamp::parse(track.tags.get("DURATION")?)?;
+
+	let offset = match track.start {
+		Some(offset) if streamtype != StreamType::Subtitle => offset,
+		_ => start,
+	};
+
+	let id = match track.tags.get("SOURCE_ID") {
+		Some(id) => id.clone(),
+		None => format!("{streamtype}{index}"),
+	};
+
+	Some(Stream {
+		path: file.to_path_buf(),
+		index: index as i32,
+		streamtype,
+		id,
+		language: track.language.clone(),
+		codec: Some(codec),
+		offset: Timestamp::from_nanos(offset),
+		duration,
+		aspect,
+		framerate,
+		height: track.height.map(|h| h as u32),
+		samplerate: track.samplerate.map(|s| s as u32),
+		channels: track.channels.map(|c| c as u32),
+		dialnorm: None,
+		dsurmode: None,
+	})
+}
 
-	let cmd = Command::new("ffprobe")
-		.arg(file.to_str().unwrap())
-		.arg("-of")
-		.arg("json")
-		.arg("-show_streams")
-		.arg("-probesize")
-		.arg("10G")
-		.arg("-analyzeduration")
-		.arg("10G")
-		.output();
+// Codecs of the Matroska codec IDs, by the names ffmpeg uses
+fn codec(track: &ebml::Track) -> Option<Codec> {
+	let depth = track.bitdepth.unwrap_or(16);
+
+	let codec = match track.codec.as_str() {
+		"V_MPEG4/ISO/AVC" => Codec::H264,
+		"V_MPEGH/ISO/HEVC" => Codec::Hevc,
+		"V_MPEG2" => Codec::Mpeg2Video,
+		"V_MPEG1" => Codec::Mpeg1Video,
+		"V_MPEG4/ISO/SP" | "V_MPEG4/ISO/ASP" | "V_MPEG4/ISO/AP" => Codec::Mpeg4,
+		"V_AV1" => Codec::Av1,
+		"V_VP8" => Codec::Vp8,
+		"V_VP9" => Codec::Vp9,
+		"V_FFV1" => Codec::Ffv1,
+		"V_THEORA" => Codec::Theora,
+		"V_PRORES" => Codec::ProRes,
+		"A_AC3" | "A_AC3/BSID9" | "A_AC3/BSID10" => Codec::Ac3,
+		"A_EAC3" => Codec::Eac3,
+		"A_DTS" | "A_DTS/EXPRESS" | "A_DTS/LOSSLESS" => Codec::Dts,
+		"A_TRUEHD" => Codec::TrueHd,
+		"A_MLP" => Codec::Mlp,
+		"A_FLAC" => Codec::Flac,
+		"A_OPUS" => Codec::Opus,
+		"A_VORBIS" => Codec::Vorbis,
+		"A_ALAC" => Codec::Alac,
+		"A_MPEG/L1" => Codec::Mp1,
+		"A_MPEG/L2" => Codec::Mp2,
+		"A_MPEG/L3" => Codec::Mp3,
+		"A_PCM/INT/LIT" if depth == 8 => Codec::parse("pcm_u8"),
+		"A_PCM/INT/LIT" => Codec::parse(&format!("pcm_s{depth}le")),
+		"A_PCM/INT/BIG" => Codec::parse(&format!("pcm_s{depth}be")),
+		"A_PCM/FLOAT/IEEE" => Codec::parse(&format!("pcm_f{depth}le")),
+		"S_TEXT/UTF8" => Codec::Subrip,
+		"S_TEXT/ASS" | "S_TEXT/SSA" => Codec::Ass,
+		"S_TEXT/WEBVTT" => Codec::WebVtt,
+		"S_VOBSUB" => Codec::DvdSubtitle,
+		"S_HDMV/PGS" => Codec::HdmvPgsSubtitle,
+		"S_HDMV/TEXTST" => Codec::HdmvTextSubtitle,
+		"S_DVBSUB" => Codec::DvbSubtitle,
+		id if id.starts_with("A_AAC") => Codec::Aac,
+		_ => return None,
+	};
+
+	Some(codec)
+}
 
-	let cmd = utils::check_output(cmd);
+// The frame duration is rounded to nanoseconds, so it is matched to the common rates
+//
+// For interlaced video it can be the duration of a field instead, where ffprobe
+// reports the frame rate. Higher rates are only trusted for progressive video.
+fn rate(frame: u64, progressive: bool) -> Option<(u32, u32)> {
+	let rates: &[(u32, u32)] = if progressive {
+		&[
+			(24000, 1001),
+			(24, 1),
+			(25, 1),
+			(30000, 1001),
+			(30, 1),
+			(50, 1),
+			(60000, 1001),
+			(60, 1),
+		]
+	} else {
+		&[(24000, 1001), (24, 1), (25, 1), (30000, 1001), (30, 1)]
+	};
+
+	rates
+		.iter()
+		.find(|(num, den)| {
+			let expected = 1e9 * *den as f64 / *num as f64;
+			(expected - frame as f64).abs() < 1000.0
+		})
+		.copied()
+}
 
-	if let Err(err) = cmd {
-		let name = file.to_str().unwrap();
+// Like mediainfo, the common ratios are named and others are given relative to 1
+fn aspect((width, height): (u64, u64)) -> String {
+	let ratio = width as f64 / height.max(1) as f64;
 
-		logging::error!("Failed to probe {}: {}", name, err);
-		return Err(());
+	if (ratio / (4.0 / 3.0) - 1.0).abs() < 0.01 {
+		String::from("4:3")
+	} else if (ratio / (16.0 / 9.0) - 1.0).abs() < 0.01 {
+		String::from("16:9")
+	} else {
+		format!("{ratio:.2}:1")
 	}
+}
 
-	let data = String::from_utf8(cmd.unwrap().stdout);
-	if let Err(err) = data {
-		let name = file.to_str().unwrap();
-
-		logging::error!("Failed to decode probe of {}: {}", name, err);
-		return Err(());
+fn chapters(file: &Path) -> Stream {
+	Stream {
+		path: file.to_path_buf(),
+		index: -1,
+		streamtype: StreamType::Chapters,
+		id: String::from("chapters"),
+		language: None,
+		codec: None,
+		offset: Timestamp::ZERO,
+		duration: Timestamp::ZERO,
+		aspect: None,
+		framerate: None,
+		height: None,
+		samplerate: None,
+		channels: None,
+		dialnorm: None,
+		dsurmode: None,
 	}
+}
 
-	let data = json::parse(&data.unwrap());
-	if let Err(err) = data {
-		let name = file.to_str().unwrap();
-
-		logging::error!("Failed to decode probe of {}: {}", name, err);
-		return Err(());
-	}
+// Runs ffprobe and mediainfo once for all streams of a file
+fn ffprobe(file: &Path) -> Result<Vec<Stream>> {
+	let data = tools::output(
+		tools::FFPROBE
+			.command()
+			.arg(file)
+			.arg("-of")
+			.arg("json")
+			.arg("-show_format")
+			.arg("-show_streams")
+			.arg("-show_chapters")
+			.arg("-probesize")
+			.arg("10G")
+			.arg("-analyzeduration")
+			.arg("10G"),
+	)?;
 
-	let cmd = Command::new("mediainfo")
-		.arg(file)
-		.arg("-F")
-		.arg("--Output=JSON")
-		.output();
+	let data = json::parse(&data).map_err(|e| Error::parse(file, e))?;
 
-	let cmd = utils::check_output(cmd);
-	if let Err(err) = cmd {
-		let name = file.to_str().unwrap();
+	let info = tools::output(
+		tools::MEDIAINFO
+			.command()
+			.arg(file)
+			.arg("-F")
+			.arg("--Output=JSON"),
+	)?;
 
-		logging::error!("Failed to run mediainfo on {}: {}", name, err);
-		return Err(());
-	}
+	let info = json::parse(&info).map_err(|e| Error::parse(file, e))?;
 
-	let info = String::from_utf8(cmd.unwrap().stdout);
-	if let Err(err) = info {
-		let name = file.to_str().unwrap();
+	let mut streams = Vec::<Stream>::new();
+	let count = data["format"]["nb_streams"].as_u32().unwrap_or_default();
 
-		logging::error!("Failed to decode mediainfo of {}: {}", name, err);
-		return Err(());
-	}
+	for index in 0..count {
+		let entry = data["streams"]
+			.members()
+			.find(|s| s["index"].as_u32() == Some(index))
+			.ok_or_else(|| Error::parse(file, format!("stream {index} not found")))?;
 
-	let info = json::parse(&info.unwrap());
-	if let Err(err) = info {
-		let name = file.to_str().unwrap();
+		let mediainfo = track(&info, index);
 
-		logging::error!("Failed to decode mediainfo of {}: {}", name, err);
-		return Err(());
+		streams.push(parse(file, index, entry, mediainfo));
 	}
 
-	let data = data.unwrap();
-	let info = info.unwrap();
-
-	for entry in data["streams"].members() {
-		if entry["index"].as_u32().unwrap_or_default() != index {
-			continue;
-		}
-
-		let mediainfo = &info["media"]["track"][index as usize + 1];
+	if !data["chapters"].is_empty() {
+		streams.push(chapters(file));
+	}
 
-		let streamtype = entry["codec_type"].to_string();
-		let codec = Some(entry["codec_name"].to_string());
-		let offset = entry["start_time"]
-			.to_string()
-			.parse::<f32>()
-			.unwrap_or_default();
+	Ok(streams)
+}
 
-		let tags = &entry["tags"];
+// The mediainfo track of a stream, files without a StreamOrder list the tracks in order
+fn track(info: &JsonValue, index: u32) -> &JsonValue {
+	let tracks = &info["media"]["track"];
+	let order = index.to_string();
 
-		let id = if tags.has_key("SOURCE_ID") {
-			tags["SOURCE_ID"].to_string()
-		} else {
-			format!("{streamtype}{index}")
-		};
+	// The first track of mediainfo describes the whole file
+	tracks
+		.members()
+		.find(|t| t["@type"] != "General" && t["StreamOrder"] == order.as_str())
+		.unwrap_or(&tracks[index as usize + 1])
+}
 
-		let language = if tags.has_key("language") {
-			Some(tags["language"].to_string())
+// Combines what ffprobe and mediainfo know about a stream
+fn parse(file: &Path, index: u32, entry: &JsonValue, mediainfo: &JsonValue) -> Stream {
+	// Streams ffprobe doesn't know the type of can only be copied like data
+	let streamtype = entry["codec_type"]
+		.as_str()
+		.and_then(StreamType::parse)
+		.unwrap_or(StreamType::Data);
+	let codec = entry["codec_name"].as_str().map(Codec::parse);
+	let offset = Timestamp::parse(&entry["start_time"].to_string()).unwrap_or_default();
+
+	let tags = &entry["tags"];
+
+	let id = if tags.has_key("SOURCE_ID") {
+		tags["SOURCE_ID"].to_string()
+	} else {
+		format!("{streamtype}{index}")
+	};
+
+	let language = if tags.has_key("language") {
+		Some(tags["language"].to_string())
+	} else {
+		None
+	};
+
+	// Durations in tags are written as HH:MM:SS.nnnnnnnnn
+	let duration = if tags.has_key("DURATION") {
+		Timestamp::parse(&tags["DURATION"].to_string()).unwrap_or_default()
+	} else if entry.has_key("duration") {
+		Timestamp::parse(&entry["duration"].to_string()).unwrap_or_default()
+	} else {
+		Timestamp::ZERO
+	};
+
+	// ffmpeg rounds the value, e.g. for NTSC 16:9 it will output 853:480
+	// which is slightly below 16:9. Mediainfo reports the correct value.
+	let aspect = if streamtype == StreamType::Video {
+		Some(mediainfo["DisplayAspectRatio_String"].to_string())
+	} else {
+		None
+	};
+
+	let framerate = if streamtype == StreamType::Video {
+		Some(utils::framerate(&entry["avg_frame_rate"].to_string()))
+	} else {
+		None
+	};
+
+	let height = if streamtype == StreamType::Video {
+		entry["height"].as_u32()
+	} else {
+		None
+	};
+
+	let samplerate = if streamtype == StreamType::Audio {
+		mediainfo["SamplingRate"].to_string().parse::<u32>().ok()
+	} else {
+		None
+	};
+
+	let channels = if streamtype == StreamType::Audio {
+		entry["channels"].as_u32()
+	} else {
+		None
+	};
+
+	let dialnorm = if streamtype == StreamType::Audio {
+		let extra = &mediainfo["extra"];
+
+		if extra.has_key("dialnorm_Average") {
+			extra["dialnorm_Average"].to_string().parse::<i32>().ok()
 		} else {
 			None
-		};
+		}
+	} else {
+		None
+	};
 
-		let duration = if tags.has_key("DURATION") {
-			let dur = tags["DURATION"].to_string();
-			let mut split = dur.split(':');
+	let dsurmode = if streamtype == StreamType::Audio {
+		let extra = &mediainfo["extra"];
 
-			let hours = split.next().unwrap().parse::<f32>().unwrap_or_default();
-			let minutes = split.next().unwrap().parse::<f32>().unwrap_or_default();
-			let seconds = split.next().unwrap().parse::<f32>().unwrap_or_default();
+		if extra.has_key("dsurmod") {
+			extra["dsurmod"].to_string().parse::<u32>().ok()
+		} else {
+			None
+		}
+	} else {
+		None
+	};
+
+	Stream {
+		path: file.to_path_buf(),
+		index: index as i32,
+		streamtype,
+		id,
+		language,
+		codec,
+		offset,
+		duration,
+		aspect,
+		framerate,
+		height,
+		samplerate,
+		channels,
+		dialnorm,
+		dsurmode,
+	}
+}
 
-			seconds + minutes * 60.0 + hours * 60.0 * 60.0
-		} else if entry.has_key("duration") {
-			let dur = entry["duration"].to_string();
+// Probing large files takes a while, the results are kept until the file changes
+fn cache_file(cache: &Path, file: &Path) -> PathBuf {
+	let name = file.file_name().unwrap_or_default().to_str().unwrap();
+	cache.join(format!("{name}.probe.json"))
+}
 
-			dur.parse::<f32>().unwrap_or_default()
-		} else {
-			0.0
-		};
+pub fn key(file: &Path) -> Option<String> {
+	let meta = std::fs::metadata(file).ok()?;
+	let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
 
-		// ffmpeg rounds the value, e.g. for NTSC 16:9 it will output 853:480
-		// which is slightly below 16:9. Mediainfo reports the correct value.
-		let aspect = if streamtype == "video" {
-			Some(mediainfo["DisplayAspectRatio_String"].to_string())
-		} else {
-			None
-		};
+	Some(format!(
+		"{} {} {}",
+		file.to_str().unwrap(),
+		meta.len(),
+		modified.as_nanos()
+	))
+}
 
-		let framerate = if streamtype == "video" {
-			Some(utils::framerate(&entry["avg_frame_rate"].to_string()))
-		} else {
-			None
-		};
+fn cached(cache: &Path, file: &Path) -> Option<Vec<Stream>> {
+	let path = cache_file(cache, file);
 
-		let samplerate = if streamtype == "audio" {
-			mediainfo["SamplingRate"].to_string().parse::<u32>().ok()
-		} else {
-			None
-		};
+	let data = std::fs::read_to_string(&path).ok()?;
+	let mut data = json::parse(&data).ok()?;
 
-		let channels = if streamtype == "audio" {
-			entry["channels"].as_u32()
-		} else {
-			None
-		};
+	if data["version"].as_u32() != Some(VERSION)
+		|| data["key"].as_str() != Some(key(file)?.as_str())
+	{
+		return None;
+	}
 
-		let dialnorm = if streamtype == "audio" {
-			let extra = &mediainfo["extra"];
+	data["streams"]
+		.members_mut()
+		.map(|s| Stream::from_json(&path, s.take()).ok())
+		.collect()
+}
 
-			if extra.has_key("dialnorm_Average") {
-				extra["dialnorm_Average"].to_string().parse::<i32>().ok()
-			} else {
-				None
-			}
-		} else {
-			None
-		};
+fn store(cache: &Path, file: &Path, streams: &[Stream]) -> Result<()> {
+	let path = cache_file(cache, file);
 
-		let dsurmode = if streamtype == "audio" {
-			let extra = &mediainfo["extra"];
+	let data = object! {
+		version: VERSION,
+		key: key(file),
+		streams: streams.iter().map(Stream::to_json).collect::<Vec<_>>(),
+	};
 
-			if extra.has_key("dsurmod") {
-				extra["dsurmod"].to_string().parse::<u32>().ok()
-			} else {
-				None
-			}
-		} else {
-			None
-		};
+	std::fs::write(&path, data.dump()).map_err(|e| Error::io(&path, e))
+}
 
-		let stream = Stream {
-			path: file.to_path_buf(),
-			index: index as i32,
-			streamtype,
-			id,
-			language,
-			codec,
-			offset,
-			duration,
-			aspect,
-			framerate,
-			samplerate,
-			channels,
-			dialnorm,
-			dsurmode,
-		};
+pub fn write(streams: &Vec<Stream>, path: &Path) -> Result<()> {
+	let temp = path.with_extension("temp.mkv");
 
-		return Ok(stream);
+	for (tool, args) in commands(streams, path) {
+		tools::run(tool.command().args(args))?;
 	}
 
-	Err(())
+	std::fs::remove_file(&temp).map_err(|e| Error::io(&temp, e))
 }
 
-pub fn write(streams: &Vec<Stream>, path: &Path) -> Result<(), ()> {
+pub fn commands(streams: &Vec<Stream>, path: &Path) -> Vec<(Tool, Vec<String>)> {
 	let mut args = Vec::<String>::new();
 	let mut chapters = Vec::<&Stream>::new();
-	let mut choffset: f32 = 0.0;
+	let mut choffset = Timestamp::ZERO;
 
 	let temp = path.with_extension("temp.mkv");
 
 	for stream in streams {
-		if stream.streamtype == "chapters" {
+		if stream.streamtype == StreamType::Chapters {
 			chapters.push(stream);
 		} else {
 			args.push_str("-itsoffset");
@@ -477,7 +891,7 @@ pub fn write(streams: &Vec<Stream>, path: &Path) -> Result<(), ()> {
 	// affected, so this shift needs to be applied manually.
 	for chap in chapters {
 		let mut offset = chap.offset;
-		if choffset < 0.0 {
+		if choffset < Timestamp::ZERO {
 			offset -= choffset
 		}
 
@@ -489,7 +903,7 @@ pub fn write(streams: &Vec<Stream>, path: &Path) -> Result<(), ()> {
 	}
 
 	for (i, stream) in streams.iter().enumerate() {
-		if stream.streamtype == "chapters" {
+		if stream.streamtype == StreamType::Chapters {
 			args.push_str("-map_chapters");
 			args.push(i.to_string());
 			continue;
@@ -498,6 +912,11 @@ pub fn write(streams: &Vec<Stream>, path: &Path) -> Result<(), ()> {
 		args.push_str("-map");
 		args.push(format!("{}:{}", i, stream.index));
 
+		// Attachments keep their file name and MIME type, which the muxer needs
+		if stream.streamtype == StreamType::Attachment {
+			continue;
+		}
+
 		// Unset some metadata
 		let meta = format!("-metadata:s:{i}");
 		args.push(meta.clone());
@@ -510,13 +929,13 @@ pub fn write(streams: &Vec<Stream>, path: &Path) -> Result<(), ()> {
 		args.push(meta.clone());
 
 		// Set language metadata
-		if stream.streamtype == "video" {
-			args.push_str("language=");
-		} else {
-			args.push(format!(
+		match stream.streamtype {
+			StreamType::Video => args.push_str("language="),
+			StreamType::Audio | StreamType::Subtitle | StreamType::Data => args.push(format!(
 				"language={}",
 				stream.language.as_deref().unwrap_or_default()
-			));
+			)),
+			StreamType::Attachment | StreamType::Chapters => {}
 		}
 	}
 
@@ -535,30 +954,113 @@ pub fn write(streams: &Vec<Stream>, path: &Path) -> Result<(), ()> {
 	args.push_str("-y");
 	args.push_str(temp.to_str().unwrap());
 
-	let cmd = Command::new("ffmpeg")
-		.args(args)
-		.execute_check_exit_status_code(0);
+	let mut merge = Vec::<String>::new();
+
+	merge.push_str("-o");
+	merge.push_str(path.to_str().unwrap());
+	merge.push_str(temp.to_str().unwrap());
+
+	vec![(tools::FFMPEG, args), (tools::MKVMERGE, merge)]
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
 
-	if let Err(err) = cmd {
-		logging::error!("Failed to run ffmpeg: {}", err);
-		return Err(());
+	fn track(kind: u64, codec: &str) -> ebml::Track {
+		let mut track = ebml::Track {
+			kind,
+			codec: String::from(codec),
+			..Default::default()
+		};
+
+		track
+			.tags
+			.insert(String::from("DURATION"), String::from("00:23:40.120000000"));
+		track
 	}
-	let cmd = Command::new("mkvmerge")
-		.arg("-o")
-		.arg(path)
-		.arg(&temp)
-		.execute_check_exit_status_code(0);
 
-	if let Err(err) = cmd {
-		logging::error!("Failed to run mkvmerge: {}", err);
-		return Err(());
+	#[test]
+	fn codecs() {
+		assert_eq!(codec(&track(1, "V_MPEG4/ISO/AVC")), Some(Codec::H264));
+		assert_eq!(codec(&track(1, "V_MPEGH/ISO/HEVC")), Some(Codec::Hevc));
+		assert_eq!(codec(&track(2, "A_AC3/BSID9")), Some(Codec::Ac3));
+		assert_eq!(codec(&track(2, "A_AAC/MPEG4/LC/SBR")), Some(Codec::Aac));
+		assert_eq!(codec(&track(2, "A_DTS/LOSSLESS")), Some(Codec::Dts));
+		assert_eq!(
+			codec(&track(17, "S_HDMV/PGS")),
+			Some(Codec::HdmvPgsSubtitle)
+		);
+		assert_eq!(codec(&track(1, "V_MS/VFW/FOURCC")), None);
+
+		// PCM is named after its bit depth
+		let mut pcm = track(2, "A_PCM/INT/LIT");
+		assert_eq!(codec(&pcm), Some(Codec::PcmS16le));
+
+		pcm.bitdepth = Some(24);
+		assert_eq!(codec(&pcm), Some(Codec::PcmS24le));
+
+		pcm.codec = String::from("A_PCM/FLOAT/IEEE");
+		pcm.bitdepth = Some(32);
+		assert_eq!(codec(&pcm), Some(Codec::PcmF32le));
 	}
 
-	let err = std::fs::remove_file(temp);
-	if let Err(err) = err {
-		logging::error!("Failed to remove file: {}", err);
-		return Err(());
+	#[test]
+	fn rates() {
+		assert_eq!(rate(41708333, true), Some((24000, 1001)));
+		assert_eq!(rate(41666667, true), Some((24, 1)));
+		assert_eq!(rate(40000000, true), Some((25, 1)));
+		assert_eq!(rate(33366667, true), Some((30000, 1001)));
+		assert_eq!(rate(16683333, true), Some((60000, 1001)));
+		assert_eq!(rate(20000000, true), Some((50, 1)));
+
+		// Interlaced video can have the duration of a field, ffprobe knows the frame rate
+		assert_eq!(rate(40000000, false), Some((25, 1)));
+		assert_eq!(rate(33366667, false), Some((30000, 1001)));
+		assert_eq!(rate(20000000, false), None);
+		assert_eq!(rate(16683333, false), None);
+
+		assert_eq!(rate(12345678, true), None);
 	}
 
-	Ok(())
+	#[test]
+	fn aspects() {
+		assert_eq!(aspect((1920, 1080)), "16:9");
+		assert_eq!(aspect((720, 540)), "4:3");
+		assert_eq!(aspect((1024, 576)), "16:9");
+		assert_eq!(aspect((1920, 800)), "2.40:1");
+		assert_eq!(aspect((1998, 1080)), "1.85:1");
+
+		// ffprobe rounds NTSC 16:9 to 853:480, which mediainfo still calls 16:9
+		assert_eq!(aspect((853, 480)), "16:9");
+	}
+
+	#[test]
+	fn fallback() {
+		let file = Path::new("test.mkv");
+
+		let audio = track_stream(file, 1, &track(2, "A_AC3"), 0).unwrap();
+		assert_eq!(audio.streamtype, StreamType::Audio);
+		assert_eq!(audio.codec, Some(Codec::Ac3));
+		assert_eq!(audio.id, "audio1");
+		assert_eq!(audio.duration, Timestamp::parse("1420.12").unwrap());
+
+		// Without the statistics tags ffprobe has to find the duration
+		let mut untagged = track(2, "A_AC3");
+		untagged.tags.clear();
+		assert!(track_stream(file, 1, &untagged, 0).is_none());
+
+		// Video needs a known frame rate
+		let mut video = track(1, "V_MPEG4/ISO/AVC");
+		video.progressive = true;
+		assert!(track_stream(file, 0, &video, 0).is_none());
+
+		video.frame = Some(41708333);
+		video.display = Some((1920, 1080));
+		let video = track_stream(file, 0, &video, 0).unwrap();
+		assert_eq!(video.framerate, Some((24000, 1001)));
+		assert_eq!(video.aspect.as_deref(), Some("16:9"));
+
+		assert!(track_stream(file, 2, &track(18, "B_VOBBTN"), 0).is_none());
+	}
 }
@@ -0,0 +1,345 @@
+use std::path::PathBuf;
+
+use crate::{
+	config::{Config, Output},
+	error::{Error, Result},
+	mkv::{Stream, StreamType},
+};
+
+// Placeholders every template can use, the named groups of the pattern add more
+const PLACEHOLDERS: &[&str] = &[
+	"config",
+	"input",
+	"item",
+	"folder",
+	"resolution",
+	"vcodec",
+	"alangs",
+	"slangs",
+];
+
+// Placeholders filled in from the processed streams, only known once processing is done
+const STREAMS: &[&str] = &["resolution", "vcodec", "alangs", "slangs"];
+
+enum Part<'a> {
+	Text(String),
+
+	// Numbers are padded with zeros to the width, like {episode:02}
+	Field { name: &'a str, width: usize },
+}
+
+fn parse(template: &str) -> std::result::Result<Vec<Part<'_>>, String> {
+	let mut parts = Vec::<Part>::new();
+	let mut text = String::new();
+	let mut rest = template;
+
+	while let Some(i) = rest.find(['{', '}']) {
+		text.push_str(&rest[..i]);
+
+		// Braces are escaped by doubling them
+		let c = &rest[i..i + 1];
+		if rest[i + 1..].starts_with(c) {
+			text.push_str(c);
+			rest = &rest[i + 2..];
+			continue;
+		}
+
+		if c == "}" {
+			return Err(String::from("unmatched } in template"));
+		}
+
+		let end = rest[i..]
+			.find('}')
+			.ok_or_else(|| String::from("unmatched { in template"))?;
+		let field = &rest[i + 1..i + end];
+
+		let (name, width) = match field.split_once(':') {
+			Some((name, width)) => match width.parse::<usize>() {
+				Ok(width) => (name, width),
+				Err(_) => return Err(format!("invalid width {width} of {name}, expected e.g. 02")),
+			},
+			None => (field, 0),
+		};
+
+		parts.push(Part::Text(std::mem::take(&mut text)));
+		parts.push(Part::Field { name, width });
+
+		rest = &rest[i + end + 1..];
+	}
+
+	text.push_str(rest);
+	parts.push(Part::Text(text));
+
+	Ok(parts)
+}
+
+// Rejects templates that can't be filled in, e.g. because of a typo in a placeholder
+pub fn check(output: &Output) -> std::result::Result<(), String> {
+	let groups = output.pattern.capture_names().flatten().collect::<Vec<_>>();
+
+	for part in parse(&output.template)? {
+		if let Part::Field { name, .. } = part {
+			if !PLACEHOLDERS.contains(&name) && !groups.contains(&name) {
+				return Err(format!("unknown placeholder {{{name}}}"));
+			}
+		}
+	}
+
+	Ok(())
+}
+
+// Whether the output can only be named after the streams are processed
+pub fn needs_streams(cfg: &Config) -> bool {
+	let parts = match &cfg.output {
+		Some(output) => parse(&output.template).unwrap_or_default(),
+		None => return false,
+	};
+
+	parts
+		.iter()
+		.any(|p| matches!(p, Part::Field { name, .. } if STREAMS.contains(name)))
+}
+
+// Path of the output relative to the output directory
+//
+// The item is the name of the item in the input folder, including the folders it is in.
+pub fn path(cfg: &Config, input: &str, item: &str, streams: &[Stream]) -> Result<PathBuf> {
+	let output = match &cfg.output {
+		Some(output) => output,
+		None => return Ok(PathBuf::from(format!("{input}/{item}.mkv"))),
+	};
+
+	let error = |reason: String| Error::config(cfg.file(), "output", reason);
+
+	let (folder, stem) = item.rsplit_once('/').unwrap_or(("", item));
+	let video = streams.iter().find(|s| s.streamtype == StreamType::Video);
+	let captures = output.pattern.captures(item);
+
+	let langs = |streamtype: StreamType| {
+		let mut langs = Vec::<&str>::new();
+
+		for stream in streams.iter().filter(|s| s.streamtype == streamtype) {
+			if let Some(lang) = stream.language.as_deref() {
+				if !langs.contains(&lang) {
+					langs.push(lang);
+				}
+			}
+		}
+
+		langs.join("+")
+	};
+
+	let mut path = String::new();
+
+	for part in parse(&output.template).map_err(error)? {
+		let (name, width) = match part {
+			Part::Text(text) => {
+				path.push_str(&text);
+				continue;
+			}
+			Part::Field { name, width } => (name, width),
+		};
+
+		let value = match name {
+			"config" => cfg.name.clone(),
+			"input" => String::from(input),
+			"item" => String::from(stem),
+			"folder" => String::from(folder),
+			"resolution" => video
+				.and_then(|v| v.height)
+				.map(|h| format!("{h}p"))
+				.unwrap_or_default(),
+			"vcodec" => video
+				.and_then(|v| v.codec.as_ref())
+				.map(|c| c.to_string())
+				.unwrap_or_default(),
+			"alangs" => langs(StreamType::Audio),
+			"slangs" => langs(StreamType::Subtitle),
+			group => {
+				let captures = captures.as_ref().ok_or_else(|| {
+					error(format!(
+						"item {item} does not match the pattern for {{{group}}}"
+					))
+				})?;
+
+				captures
+					.name(group)
+					.map(|m| String::from(m.as_str()))
+					.unwrap_or_default()
+			}
+		};
+
+		// Only the folders of the item may add folders to the output
+		let value = if name == "folder" {
+			value
+		} else {
+			value.replace(['/', '\\'], "-")
+		};
+
+		match value.parse::<u64>() {
+			Ok(number) if width > 0 => path.push_str(&format!("{number:0width$}")),
+			_ => path.push_str(&value),
+		}
+	}
+
+	// Empty placeholders can leave empty folders or spaces behind
+	let mut folders = Vec::<&str>::new();
+
+	for folder in path.split('/').map(str::trim) {
+		if folder == ".." {
+			return Err(error(format!("{path} is outside of the output directory")));
+		}
+
+		if !folder.is_empty() && folder != "." {
+			folders.push(folder);
+		}
+	}
+
+	if folders.is_empty() {
+		return Err(error(format!("empty output name for item {item}")));
+	}
+
+	Ok(PathBuf::from(format!("{}.mkv", folders.join("/"))))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+
+	use json::{object, JsonValue};
+
+	use super::*;
+	use crate::{codec::Codec, config, timestamp::Timestamp};
+
+	fn config(output: JsonValue) -> Config {
+		config::parse(Path::new("test.json"), "show", &object! { output: output }).unwrap()
+	}
+
+	fn stream(streamtype: StreamType, codec: Codec, language: Option<&str>) -> Stream {
+		Stream {
+			path: PathBuf::from("test.mkv"),
+			index: 0,
+			streamtype,
+			id: String::new(),
+			language: language.map(String::from),
+			codec: Some(codec),
+			offset: Timestamp::ZERO,
+			duration: Timestamp::ZERO,
+			aspect: None,
+			framerate: None,
+			height: Some(1080),
+			samplerate: None,
+			channels: None,
+			dialnorm: None,
+			dsurmode: None,
+		}
+	}
+
+	fn named(template: &str, item: &str, streams: &[Stream]) -> Result<String> {
+		let cfg = config(template.into());
+		path(&cfg, "Show", item, streams).map(|p| String::from(p.to_str().unwrap()))
+	}
+
+	#[test]
+	fn templates() {
+		let output = |template: &str| Output {
+			template: String::from(template),
+			pattern: regex::Regex::new(r"(?P<episode>\d+)").unwrap(),
+		};
+
+		assert!(check(&output("{config}/{{literal}} {episode:02}")).is_ok());
+		assert_eq!(
+			check(&output("{item} {season}")),
+			Err(String::from("unknown placeholder {season}"))
+		);
+		assert_eq!(
+			check(&output("{item")),
+			Err(String::from("unmatched { in template"))
+		);
+		assert_eq!(
+			check(&output("item}")),
+			Err(String::from("unmatched } in template"))
+		);
+		assert_eq!(
+			check(&output("{episode:x}")),
+			Err(String::from("invalid width x of episode, expected e.g. 02"))
+		);
+	}
+
+	#[test]
+	fn paths() {
+		let cfg = config::parse(Path::new("test.json"), "show", &object! {}).unwrap();
+		let default = path(&cfg, "Show", "Season 1/ep01", &[]).unwrap();
+		assert_eq!(default, PathBuf::from("Show/Season 1/ep01.mkv"));
+
+		// Numbers are padded, other values are used as they are
+		let name = named(
+			"{config}/S{season:02}E{episode:03} {item}",
+			"Show s1e2",
+			&[],
+		);
+		assert_eq!(name.unwrap(), "show/S01E002 Show s1e2.mkv");
+
+		let name = named("{input} - {episode:02}", "Show s1eX", &[]);
+		assert!(name
+			.unwrap_err()
+			.to_string()
+			.contains("does not match the pattern"));
+
+		// Only the folders of the item add folders, and braces can be escaped
+		let name = named("{folder}/{{{item}}}", "Season 1/ep01", &[]);
+		assert_eq!(name.unwrap(), "Season 1/{ep01}.mkv");
+
+		let cfg = config(object! { path: "{title}", pattern: "(?P<title>.*)" });
+		let name = path(&cfg, "Show", "AC/DC Live", &[]).unwrap();
+		assert_eq!(name, PathBuf::from("AC-DC Live.mkv"));
+	}
+
+	#[test]
+	fn cleanup() {
+		// Empty placeholders leave no empty folders or spaces behind
+		let name = named("{input}/{folder}/ {item} ", "ep01", &[]);
+		assert_eq!(name.unwrap(), "Show/ep01.mkv");
+
+		let err = named("../{item}", "ep01", &[]).unwrap_err().to_string();
+		assert!(
+			err.contains("../ep01 is outside of the output directory"),
+			"{err}"
+		);
+
+		let err = named("{folder}", "ep01", &[]).unwrap_err().to_string();
+		assert!(err.contains("empty output name for item ep01"), "{err}");
+	}
+
+	#[test]
+	fn streams() {
+		let streams = [
+			stream(StreamType::Video, Codec::Hevc, None),
+			stream(StreamType::Audio, Codec::Aac, Some("jpn")),
+			stream(StreamType::Audio, Codec::Ac3, Some("eng")),
+			stream(StreamType::Audio, Codec::Aac, Some("jpn")),
+			stream(StreamType::Subtitle, Codec::Ass, Some("eng")),
+		];
+
+		let template = "{item} [{resolution} {vcodec}] {alangs} {slangs}";
+		let name = named(template, "ep01", &streams).unwrap();
+		assert_eq!(name, "ep01 [1080p hevc] jpn+eng eng.mkv");
+
+		// Without streams, the placeholders are empty
+		assert_eq!(named(template, "ep01", &[]).unwrap(), "ep01 [ ].mkv");
+	}
+
+	#[test]
+	fn early() {
+		assert!(!needs_streams(
+			&config::parse(Path::new("test.json"), "show", &object! {}).unwrap()
+		));
+		assert!(!needs_streams(&config("{config}/{item} {episode}".into())));
+		assert!(!needs_streams(&config("{item} {{resolution}}".into())));
+
+		for placeholder in STREAMS {
+			let template = format!("{{item}} [{{{placeholder}}}]");
+			assert!(needs_streams(&config(template.into())), "{placeholder}");
+		}
+	}
+}
@@ -0,0 +1,90 @@
+use std::sync::{Condvar, Mutex};
+
+use crate::mkv::StreamType;
+
+struct Semaphore {
+	count: Mutex<usize>,
+	cond: Condvar,
+}
+
+impl Semaphore {
+	fn new(count: usize) -> Self {
+		Self {
+			count: Mutex::new(count.max(1)),
+			cond: Condvar::new(),
+		}
+	}
+
+	fn acquire(&self) -> Permit<'_> {
+		let mut count = self.count.lock().unwrap();
+
+		while *count == 0 {
+			count = self.cond.wait(count).unwrap();
+		}
+
+		*count -= 1;
+		Permit { semaphore: self }
+	}
+
+	fn release(&self) {
+		*self.count.lock().unwrap() += 1;
+		self.cond.notify_one();
+	}
+}
+
+pub struct Permit<'a> {
+	semaphore: &'a Semaphore,
+}
+
+impl Drop for Permit<'_> {
+	fn drop(&mut self) {
+		self.semaphore.release();
+	}
+}
+
+pub struct Scheduler {
+	items: Semaphore,
+	jobs: Semaphore,
+	video: Semaphore,
+	audio: Semaphore,
+}
+
+impl Scheduler {
+	pub fn new(jobs: usize, video: usize, audio: usize) -> Self {
+		Self {
+			items: Semaphore::new(jobs),
+			jobs: Semaphore::new(jobs),
+			video: Semaphore::new(video),
+			audio: Semaphore::new(audio),
+		}
+	}
+
+	// Limits how many items are in flight at the same time
+	pub fn item(&self) -> Permit<'_> {
+		self.items.acquire()
+	}
+
+	// Waits for a free job slot, and for a slot of the resource the job needs.
+	// The resource is always acquired first, so a job slot is never blocked by
+	// a job that is waiting for its resource.
+	pub fn job(&self, streamtype: Option<StreamType>) -> Vec<Permit<'_>> {
+		let mut permits = Vec::<Permit>::new();
+
+		match streamtype {
+			Some(StreamType::Video) => permits.push(self.video.acquire()),
+			Some(StreamType::Audio) => permits.push(self.audio.acquire()),
+
+			// Other streams and muxing only need a job slot
+			Some(
+				StreamType::Subtitle
+				| StreamType::Attachment
+				| StreamType::Data
+				| StreamType::Chapters,
+			)
+			| None => {}
+		}
+
+		permits.push(self.jobs.acquire());
+		permits
+	}
+}
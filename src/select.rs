@@ -1,25 +1,26 @@
-use json::JsonValue;
-
-use crate::{logging, mkv, utils};
+use crate::{
+	config::{Config, Selector},
+	error::{Error, Result},
+	logging, mkv, utils,
+};
 
 pub fn find(
-	cfg: &JsonValue,
+	cfg: &Config,
 	streams: &Vec<mkv::Stream>,
-) -> Result<Vec<(String, JsonValue, mkv::Stream)>, ()> {
-	let mut new = Vec::<(String, JsonValue, mkv::Stream)>::new();
+) -> Result<Vec<(String, Selector, mkv::Stream)>> {
+	let mut new = Vec::<(String, Selector, mkv::Stream)>::new();
 
-	for entry in cfg.entries() {
-		let name = String::from(entry.0);
-		let streamcfg = entry.1;
+	for entry in &cfg.streams {
+		let name = entry.name.clone();
+		let options = &entry.options;
 
-		let m = find_match(streamcfg, streams);
+		let m = find_match(options, streams);
 		if m.is_none() {
-			if is_optional(streamcfg, streams) {
+			if is_optional(options, streams) {
 				continue;
 			}
 
-			logging::error!("Could not find match for stream {}", name);
-			return Err(());
+			return Err(Error::Select { stream: name });
 		}
 
 		let (j, s) = m.unwrap();
@@ -36,11 +37,51 @@ pub fn find(
 	Ok(new)
 }
 
-fn is_optional(cfg: &JsonValue, streams: &Vec<mkv::Stream>) -> bool {
-	for option in cfg.members() {
+pub fn explain(cfg: &Config, streams: &Vec<mkv::Stream>) {
+	for entry in &cfg.streams {
+		let name = &entry.name;
+		let options = &entry.options;
+
+		logging::info!("Stream {}:", name);
+
+		for (i, option) in options.iter().enumerate() {
+			for stream in streams {
+				let reason = mismatch(option, stream);
+				let path = stream.path.to_str().unwrap();
+
+				match reason {
+					Some(reason) => logging::info!(
+						"  Option {}: {} #{} in {} rejected ({})",
+						i,
+						stream.id,
+						stream.index,
+						path,
+						reason
+					),
+					None => logging::info!(
+						"  Option {}: {} #{} in {} matches",
+						i,
+						stream.id,
+						stream.index,
+						path
+					),
+				}
+			}
+		}
+
+		match find_match(options, streams) {
+			Some((_, s)) => logging::info!("  Selected {} #{}", s.id, s.index),
+			None if is_optional(options, streams) => logging::info!("  Missing (optional)"),
+			None => logging::info!("  No match!"),
+		}
+	}
+}
+
+fn is_optional(options: &[Selector], streams: &Vec<mkv::Stream>) -> bool {
+	for option in options {
 		let mut found = true;
 
-		if option["missing"] != true {
+		if !option.missing {
 			continue;
 		}
 
@@ -58,8 +99,8 @@ fn is_optional(cfg: &JsonValue, streams: &Vec<mkv::Stream>) -> bool {
 	false
 }
 
-fn find_match(cfg: &JsonValue, streams: &Vec<mkv::Stream>) -> Option<(JsonValue, mkv::Stream)> {
-	for option in cfg.members() {
+fn find_match(options: &[Selector], streams: &Vec<mkv::Stream>) -> Option<(Selector, mkv::Stream)> {
+	for option in options {
 		for stream in streams {
 			if !check_match(option, stream) {
 				continue;
@@ -72,35 +113,54 @@ fn find_match(cfg: &JsonValue, streams: &Vec<mkv::Stream>) -> Option<(JsonValue,
 	None
 }
 
-fn check_match(cfg: &JsonValue, stream: &mkv::Stream) -> bool {
-	if cfg["missing"] == true {
-		return false;
+fn check_match(cfg: &Selector, stream: &mkv::Stream) -> bool {
+	mismatch(cfg, stream).is_none()
+}
+
+fn mismatch(cfg: &Selector, stream: &mkv::Stream) -> Option<String> {
+	if cfg.missing {
+		return Some(String::from("option only applies to missing streams"));
+	}
+
+	if let Some(streamtype) = cfg.streamtype {
+		if streamtype != stream.streamtype {
+			return Some(format!("type {} != {}", stream.streamtype, streamtype));
+		}
 	}
 
-	if cfg.has_key("type") && cfg["type"] != stream.streamtype.as_str() {
-		return false;
+	let language = stream.language.as_deref().unwrap_or("None");
+	if let Some(lang) = &cfg.lang {
+		if lang != language {
+			return Some(format!("lang {} != {}", language, lang));
+		}
 	}
 
-	if cfg.has_key("lang") && cfg["lang"] != stream.language.as_deref().unwrap_or("None") {
-		return false;
+	if let Some(ids) = &cfg.id {
+		if !ids.contains(&stream.id) {
+			return Some(format!("id {} not in {:?}", stream.id, ids));
+		}
 	}
 
-	if cfg.has_key("id") && !cfg["id"].contains(stream.id.as_str()) {
-		return false;
+	if !check_file(cfg, stream) {
+		return Some(format!(
+			"file does not match {:?}",
+			cfg.file.as_ref().unwrap()
+		));
 	}
 
-	check_file(cfg, stream)
+	None
 }
 
-fn check_file(cfg: &JsonValue, stream: &mkv::Stream) -> bool {
-	if !cfg.has_key("file") {
-		return true;
-	}
+fn check_file(cfg: &Selector, stream: &mkv::Stream) -> bool {
+	let files = match &cfg.file {
+		Some(files) => files,
+		None => return true,
+	};
 
 	let mut m = false;
 	let path = stream.path.to_str().unwrap_or_default().to_string();
 
-	for entry in cfg["file"].members() {
+	for entry in files {
 		let regex = fnmatch_regex::glob_to_regex(format!("*/{entry}/*").as_str());
 		if regex.is_err() {
 			continue;
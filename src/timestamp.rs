@@ -0,0 +1,239 @@
+use std::{
+	fmt,
+	ops::{AddAssign, Div, DivAssign, SubAssign},
+};
+
+use crate::utils;
+
+const SECOND: i128 = 1_000_000_000;
+
+// Offsets and durations in nanoseconds, exact through any number of speed changes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(i64);
+
+impl Timestamp {
+	pub const ZERO: Self = Self(0);
+
+	pub fn from_nanos(nanos: i64) -> Self {
+		Self(nanos)
+	}
+
+	// Only for values that were never exact, e.g. JSON numbers in the configuration
+	pub fn from_seconds(seconds: f64) -> Self {
+		Self((seconds * SECOND as f64).round() as i64)
+	}
+
+	// The duration of a number of frames
+	pub fn from_frames(frames: u64, framerate: (u32, u32)) -> Self {
+		let nanos = frames as i128 * framerate.1 as i128 * SECOND;
+		Self(divide(nanos, framerate.0 as i128) as i64)
+	}
+
+	// Seconds like 1.5 or -0.042, or a duration like 01:23:45.500000000
+	pub fn parse(value: &str) -> Option<Self> {
+		let (negative, value) = match value.trim().strip_prefix('-') {
+			Some(value) => (true, value),
+			None => (false, value.trim()),
+		};
+
+		let mut parts = value.rsplit(':');
+		let seconds = parts.next()?;
+
+		let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+		if !fraction.chars().all(|c| c.is_ascii_digit()) {
+			return None;
+		}
+
+		if whole.is_empty() && fraction.is_empty() {
+			return None;
+		}
+
+		// Anything below a nanosecond is dropped, .5 has no whole seconds
+		let digits = &fraction[..fraction.len().min(9)];
+		let whole = if whole.is_empty() { "0" } else { whole };
+		let mut nanos = whole.parse::<i64>().ok()? * SECOND as i64;
+
+		if !digits.is_empty() {
+			nanos += digits.parse::<i64>().ok()? * 10i64.pow(9 - digits.len() as u32);
+		}
+
+		let mut unit = 60 * SECOND as i64;
+		for part in parts.take(2) {
+			nanos = nanos.checked_add(part.parse::<i64>().ok()?.checked_mul(unit)?)?;
+			unit *= 60;
+		}
+
+		Some(Self(if negative { -nanos } else { nanos }))
+	}
+
+	// Whole seconds, rounded towards zero
+	pub fn seconds(self) -> i64 {
+		self.0 / SECOND as i64
+	}
+
+	// How many times longer this is than the other, (1, 1) if one of them is empty
+	pub fn ratio(self, other: Self) -> (u64, u64) {
+		if self.0 <= 0 || other.0 <= 0 {
+			return (1, 1);
+		}
+
+		utils::reduce(self.0 as u64, other.0 as u64)
+	}
+}
+
+// Rounds to the nearest nanosecond instead of towards zero
+fn divide(num: i128, den: i128) -> i128 {
+	let half = den / 2;
+
+	if num < 0 {
+		(num - half) / den
+	} else {
+		(num + half) / den
+	}
+}
+
+// Divides a number of ticks in any timebase by a speedup, rounded to the nearest tick
+pub fn slow(ticks: i64, (num, den): (u64, u64)) -> i64 {
+	if num == 0 {
+		return ticks;
+	}
+
+	divide(ticks as i128 * den as i128, num as i128) as i64
+}
+
+// A speedup of num/den makes everything shorter by that factor
+impl Div<(u64, u64)> for Timestamp {
+	type Output = Self;
+
+	fn div(self, speedup: (u64, u64)) -> Self {
+		Self(slow(self.0, speedup))
+	}
+}
+
+impl DivAssign<(u64, u64)> for Timestamp {
+	fn div_assign(&mut self, speedup: (u64, u64)) {
+		*self = *self / speedup;
+	}
+}
+
+impl AddAssign for Timestamp {
+	fn add_assign(&mut self, other: Self) {
+		self.0 += other.0;
+	}
+}
+
+impl SubAssign for Timestamp {
+	fn sub_assign(&mut self, other: Self) {
+		self.0 -= other.0;
+	}
+}
+
+// Seconds without trailing zeros, which ffmpeg and the configuration read back exactly
+impl fmt::Display for Timestamp {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let sign = if self.0 < 0 { "-" } else { "" };
+		let nanos = self.0.unsigned_abs();
+
+		let whole = nanos / SECOND as u64;
+		let fraction = format!("{:09}", nanos % SECOND as u64);
+		let fraction = fraction.trim_end_matches('0');
+
+		if fraction.is_empty() {
+			write!(f, "{sign}{whole}")
+		} else {
+			write!(f, "{sign}{whole}.{fraction}")
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn seconds(value: &str) -> Timestamp {
+		Timestamp::parse(value).unwrap()
+	}
+
+	#[test]
+	fn parse() {
+		assert_eq!(seconds("1.5"), Timestamp::from_nanos(1_500_000_000));
+		assert_eq!(seconds(".5"), Timestamp::from_nanos(500_000_000));
+		assert_eq!(seconds("-0.042"), Timestamp::from_nanos(-42_000_000));
+		assert_eq!(seconds("-.5"), Timestamp::from_nanos(-500_000_000));
+		assert_eq!(seconds(" 12 "), Timestamp::from_nanos(12_000_000_000));
+
+		assert_eq!(seconds("45:00"), seconds("2700"));
+		assert_eq!(seconds("01:23:45.500000000"), seconds("5025.5"));
+		assert_eq!(seconds("-1:00:00"), seconds("-3600"));
+
+		// Digits below a nanosecond are dropped
+		assert_eq!(
+			seconds("1.1234567899"),
+			Timestamp::from_nanos(1_123_456_789)
+		);
+
+		for invalid in ["", ".", "-", "abc", "1.5x", "1:x:00", "1.-5"] {
+			assert_eq!(Timestamp::parse(invalid), None, "{invalid}");
+		}
+	}
+
+	#[test]
+	fn display() {
+		assert_eq!(Timestamp::ZERO.to_string(), "0");
+		assert_eq!(seconds("90").to_string(), "90");
+		assert_eq!(seconds("1.500").to_string(), "1.5");
+		assert_eq!(seconds("-0.042").to_string(), "-0.042");
+		assert_eq!(Timestamp::from_nanos(1).to_string(), "0.000000001");
+	}
+
+	#[test]
+	fn frames() {
+		assert_eq!(Timestamp::from_frames(25, (25, 1)), seconds("1"));
+		assert_eq!(Timestamp::from_frames(24, (24000, 1001)), seconds("1.001"));
+
+		// A single NTSC frame is rounded to the nearest nanosecond
+		let frame = Timestamp::from_frames(1, (30000, 1001));
+		assert_eq!(frame, Timestamp::from_nanos(33_366_667));
+	}
+
+	#[test]
+	fn ratio() {
+		assert_eq!(seconds("2").ratio(seconds("1")), (2, 1));
+		assert_eq!(seconds("1.001").ratio(seconds("1")), (1001, 1000));
+		assert_eq!(Timestamp::ZERO.ratio(seconds("1")), (1, 1));
+		assert_eq!(seconds("1").ratio(seconds("-1")), (1, 1));
+	}
+
+	#[test]
+	fn slow() {
+		assert_eq!(super::slow(1000, (25, 24)), 960);
+		assert_eq!(super::slow(-1001, (1001, 1000)), -1000);
+		assert_eq!(super::slow(1000, (0, 1)), 1000);
+
+		// Halves are rounded away from zero
+		assert_eq!(super::slow(1, (2, 1)), 1);
+		assert_eq!(super::slow(-1, (2, 1)), -1);
+
+		let mut timestamp = seconds("-0.125");
+		timestamp /= (1001, 960);
+		assert_eq!(timestamp, Timestamp::from_nanos(-119_880_120));
+	}
+
+	// Streams save timestamps as text, reading them back must not change them
+	#[test]
+	fn round_trip() {
+		let mut timestamp = seconds("1420.12");
+
+		for _ in 0..10 {
+			timestamp /= (25, 24);
+			timestamp /= (24000, 25025);
+
+			assert_eq!(seconds(&timestamp.to_string()), timestamp);
+		}
+
+		for nanos in [0, 1, -1, 999_999_999, -1_000_000_001, i64::MAX / 2] {
+			let timestamp = Timestamp::from_nanos(nanos);
+			assert_eq!(seconds(&timestamp.to_string()), timestamp);
+		}
+	}
+}
@@ -0,0 +1,460 @@
+use std::{
+	cell::RefCell,
+	collections::BTreeMap,
+	fs::{File, OpenOptions},
+	io::{Read, Write},
+	path::{Path, PathBuf},
+	process::{Child, ChildStdout, Command, Stdio},
+	sync::{Arc, Mutex, OnceLock},
+	thread::JoinHandle,
+};
+
+use json::JsonValue;
+
+use crate::{
+	config,
+	error::{Error, Result},
+	logging, utils,
+};
+
+// Number of stderr lines that are kept for error messages
+const TAIL: usize = 10;
+
+// Per machine binary paths and default arguments of the tools
+static SETTINGS: OnceLock<BTreeMap<String, Settings>> = OnceLock::new();
+
+// Version output of the tools that were checked, part of the stage fingerprints
+static VERSIONS: Mutex<BTreeMap<&str, String>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+	// Directory the output of the tools is written to, set while a stage runs
+	static LOGS: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+#[derive(Default)]
+struct Settings {
+	path: Option<String>,
+	args: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Check {
+	// Run the tool with the given arguments and expect an exit code
+	Run(&'static [&'static str], i32),
+
+	// The tool cannot be run without side effects, only look it up in PATH
+	Path,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Tool {
+	pub name: &'static str,
+	check: Check,
+}
+
+pub const FFMPEG: Tool = Tool {
+	name: "ffmpeg",
+	check: Check::Run(&["-version"], 0),
+};
+
+pub const FFPROBE: Tool = Tool {
+	name: "ffprobe",
+	check: Check::Run(&["-version"], 0),
+};
+
+pub const MEDIAINFO: Tool = Tool {
+	name: "mediainfo",
+	check: Check::Run(&["--version"], 0),
+};
+
+pub const MKVMERGE: Tool = Tool {
+	name: "mkvmerge",
+	check: Check::Run(&["--version"], 0),
+};
+
+pub const MKVEXTRACT: Tool = Tool {
+	name: "mkvextract",
+	check: Check::Run(&["--version"], 0),
+};
+
+pub const VSPIPE: Tool = Tool {
+	name: "vspipe",
+	check: Check::Run(&["--version"], 0),
+};
+
+pub const D2VWITCH: Tool = Tool {
+	name: "d2vwitch",
+	check: Check::Run(&["--version"], 0),
+};
+
+pub const AVS2YUV: Tool = Tool {
+	name: "avs2yuv",
+	check: Check::Run(&[], 2),
+};
+
+pub const DGINDEX: Tool = Tool {
+	name: "DGIndex",
+	check: Check::Path,
+};
+
+impl Tool {
+	pub fn path(&self) -> &str {
+		let settings = SETTINGS.get().and_then(|s| s.get(self.name));
+
+		settings
+			.and_then(|s| s.path.as_deref())
+			.unwrap_or(self.name)
+	}
+
+	pub fn args(&self) -> &[String] {
+		let settings = SETTINGS.get().and_then(|s| s.get(self.name));
+
+		settings.map(|s| s.args.as_slice()).unwrap_or_default()
+	}
+
+	pub fn command(&self) -> Command {
+		let mut cmd = Command::new(self.path());
+		cmd.args(self.args());
+		cmd
+	}
+
+	pub fn cmdline(&self, args: &[String]) -> String {
+		utils::cmdline(self.path(), &[self.args(), args].concat())
+	}
+}
+
+pub fn init(config: &Path, paths: &[String], args: &[String]) -> Result<()> {
+	let mut settings = BTreeMap::<String, Settings>::new();
+
+	if let Some(path) = config::find(&config.join(config::SETTINGS))? {
+		let data = config::read(&path)?;
+
+		load(&path, &mut settings, &data["tools"])?;
+	}
+
+	// Commandline overrides in the form of <tool>=<value>
+	for entry in paths {
+		let (name, path) = split(entry)?;
+		settings.entry(name).or_default().path = Some(path);
+	}
+
+	for entry in args {
+		let (name, args) = split(entry)?;
+		let args = args.split_whitespace().map(String::from).collect();
+
+		settings.entry(name).or_default().args = args;
+	}
+
+	let _ = SETTINGS.set(settings);
+	Ok(())
+}
+
+fn load(file: &Path, settings: &mut BTreeMap<String, Settings>, tools: &JsonValue) -> Result<()> {
+	for (name, value) in tools.entries() {
+		let entry = settings.entry(String::from(name)).or_default();
+
+		// A plain string is only the path to the binary
+		if value.is_string() {
+			entry.path = Some(value.to_string());
+			continue;
+		}
+
+		if !value.is_object() {
+			let path = format!("tools.{name}");
+			return Err(Error::config(file, &path, "expected a path or an object"));
+		}
+
+		for (key, value) in value.entries() {
+			let path = format!("tools.{name}.{key}");
+
+			match key {
+				"path" => match value.as_str() {
+					Some(value) => entry.path = Some(String::from(value)),
+					None => return Err(Error::config(file, &path, "expected a string")),
+				},
+				// Arguments are a list, or split on whitespace like --tool-args
+				"args" if value.is_string() => {
+					let args = value.as_str().unwrap().split_whitespace();
+					entry.args.extend(args.map(String::from));
+				}
+				"args" if value.is_array() => {
+					for (i, arg) in value.members().enumerate() {
+						match arg.as_str() {
+							Some(arg) => entry.args.push(String::from(arg)),
+							None => {
+								let path = format!("{path}[{i}]");
+								return Err(Error::config(file, &path, "expected a string"));
+							}
+						}
+					}
+				}
+				"args" => {
+					let reason = "expected a list of arguments or a string";
+					return Err(Error::config(file, &path, reason));
+				}
+				_ => return Err(Error::config(file, &path, "unknown key")),
+			}
+		}
+	}
+
+	Ok(())
+}
+
+fn split(entry: &str) -> Result<(String, String)> {
+	let split = entry.split_once('=');
+	if split.is_none() {
+		let reason = format!("invalid tool setting {entry}, expected <tool>=<value>");
+		return Err(Error::config(Path::new("commandline"), "", reason));
+	}
+
+	let (name, value) = split.unwrap();
+	Ok((String::from(name), String::from(value)))
+}
+
+// Runs a command and checks that it exited successfully
+pub fn run(cmd: &mut Command) -> Result<()> {
+	status(cmd, &[0])
+}
+
+// Runs a command and checks that it exited with one of the given codes
+pub fn status(cmd: &mut Command, codes: &[i32]) -> Result<()> {
+	start(cmd, Some(false))?.wait(codes).map(|_| ())
+}
+
+// Runs a command and returns what it wrote to stdout
+pub fn output(cmd: &mut Command) -> Result<String> {
+	start(cmd, Some(true))?.wait(&[0])
+}
+
+// Starts a command whose stdout is read by the caller, e.g. piped into another command
+pub fn spawn(cmd: &mut Command) -> Result<Process> {
+	start(cmd, None)
+}
+
+// Makes all commands run by func write their output to <dir>/<tool>.log
+pub fn logged<F, R>(dir: &Path, func: F) -> R
+where
+	F: FnOnce() -> R,
+{
+	let prev = LOGS.replace(Some(dir.to_path_buf()));
+	let result = func();
+
+	LOGS.set(prev);
+	result
+}
+
+pub struct Process {
+	command: String,
+	log: Option<PathBuf>,
+	child: Child,
+	stdout: Option<JoinHandle<Vec<u8>>>,
+	stderr: JoinHandle<Vec<u8>>,
+}
+
+impl Process {
+	pub fn stdout(&mut self) -> Option<ChildStdout> {
+		self.child.stdout.take()
+	}
+
+	pub fn kill(&mut self) {
+		let _ = self.child.kill();
+	}
+
+	// Waits for the command to exit, returns stdout if it was kept
+	pub fn wait(mut self, codes: &[i32]) -> Result<String> {
+		let status = self.child.wait().map_err(|source| Error::Spawn {
+			command: self.command.clone(),
+			source,
+		})?;
+
+		let stdout = self.stdout.map(|h| h.join().unwrap()).unwrap_or_default();
+		let stderr = self.stderr.join().unwrap();
+
+		let code = status.code();
+		if code.is_none() || !codes.contains(&code.unwrap()) {
+			let stderr = String::from_utf8_lossy(&stderr);
+
+			return Err(Error::Exit {
+				command: self.command,
+				code,
+				stderr: tail(&stderr),
+				log: self.log,
+			});
+		}
+
+		Ok(String::from_utf8_lossy(&stdout).into_owned())
+	}
+}
+
+// If keep is None, stdout is left to the caller, otherwise it is logged and only kept if requested
+fn start(cmd: &mut Command, keep: Option<bool>) -> Result<Process> {
+	let command = describe(cmd);
+
+	let program = PathBuf::from(cmd.get_program());
+	let name = program.file_stem().unwrap_or_default().to_string_lossy();
+
+	let dir = LOGS.with_borrow(|d| d.clone());
+	let log = dir.map(|d| d.join(format!("{name}.log")));
+
+	let file = match &log {
+		Some(log) => {
+			let mut file = OpenOptions::new()
+				.create(true)
+				.append(true)
+				.open(log)
+				.map_err(|e| Error::io(log, e))?;
+
+			writeln!(file, "$ {command}").map_err(|e| Error::io(log, e))?;
+			Some(Arc::new(Mutex::new(file)))
+		}
+		None => None,
+	};
+
+	let mut child = cmd
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|source| Error::Spawn {
+			command: command.clone(),
+			source,
+		})?;
+
+	let stdout = match keep {
+		Some(keep) => Some(tee(child.stdout.take().unwrap(), file.clone(), keep)),
+		None => None,
+	};
+
+	let stderr = tee(child.stderr.take().unwrap(), file, true);
+
+	Ok(Process {
+		command,
+		log,
+		child,
+		stdout,
+		stderr,
+	})
+}
+
+// Copies everything written to a pipe into the log, optionally keeping it in memory
+fn tee<R>(mut pipe: R, log: Option<Arc<Mutex<File>>>, keep: bool) -> JoinHandle<Vec<u8>>
+where
+	R: Read + Send + 'static,
+{
+	std::thread::spawn(move || {
+		let mut data = Vec::<u8>::new();
+		let mut buf = [0u8; 8192];
+
+		loop {
+			let len = match pipe.read(&mut buf) {
+				Ok(0) | Err(_) => break,
+				Ok(len) => len,
+			};
+
+			// A failing log should not fail the command
+			if let Some(log) = &log {
+				let _ = log.lock().unwrap().write_all(&buf[..len]);
+			}
+
+			if keep {
+				data.extend_from_slice(&buf[..len]);
+			}
+		}
+
+		data
+	})
+}
+
+pub fn describe(cmd: &Command) -> String {
+	let program = cmd.get_program().to_string_lossy();
+	let args = cmd
+		.get_args()
+		.map(|a| a.to_string_lossy().into_owned())
+		.collect::<Vec<_>>();
+
+	utils::cmdline(&program, &args)
+}
+
+pub fn tail(output: &str) -> String {
+	// Progress output is overwritten using carriage returns
+	let lines = output
+		.split(['\n', '\r'])
+		.filter(|l| !l.trim().is_empty())
+		.collect::<Vec<_>>();
+
+	let start = lines.len().saturating_sub(TAIL);
+	lines[start..].join("\n")
+}
+
+pub fn add(tools: &mut Vec<Tool>, new: &[Tool]) {
+	for tool in new {
+		if !tools.contains(tool) {
+			tools.push(*tool);
+		}
+	}
+}
+
+// Identifies the binary and default arguments of a tool, changes when the tool is updated
+pub fn version(tool: &Tool) -> String {
+	if let Some(version) = VERSIONS.lock().unwrap().get(tool.name) {
+		return version.clone();
+	}
+
+	probe(tool).unwrap_or_default()
+}
+
+fn probe(tool: &Tool) -> Result<String> {
+	let mut version = tool.cmdline(&[]);
+
+	if let Check::Run(args, code) = tool.check {
+		let output = start(Command::new(tool.path()).args(args), Some(true))?.wait(&[code])?;
+		let line = output.lines().find(|l| !l.trim().is_empty());
+
+		version = format!("{version} ({})", line.unwrap_or_default().trim());
+	}
+
+	VERSIONS.lock().unwrap().insert(tool.name, version.clone());
+	Ok(version)
+}
+
+pub fn check(tools: &[Tool]) -> bool {
+	let mut missing = Vec::<&str>::new();
+
+	for tool in tools {
+		let found = match tool.check {
+			Check::Run(..) => {
+				let version = probe(tool);
+
+				if let Err(err) = &version {
+					logging::error!("{}", err);
+				}
+
+				version.is_ok()
+			}
+			Check::Path => {
+				let found = std::env::var_os("PATH")
+					.map(|p| std::env::split_paths(&p).any(|d| d.join(tool.path()).is_file()))
+					.unwrap_or(false);
+
+				// An absolute path does not need to be looked up
+				let found = found || Path::new(tool.path()).is_file();
+
+				if !found {
+					logging::error!("Failed to find {} in PATH", tool.name);
+				}
+
+				found
+			}
+		};
+
+		if !found {
+			missing.push(tool.name);
+		}
+	}
+
+	if !missing.is_empty() {
+		logging::error!("Missing required tools: {}", missing.join(", "));
+		return false;
+	}
+
+	true
+}
@@ -1,6 +1,4 @@
-use std::io;
-
-use crate::mkv;
+use crate::mkv::{self, StreamType};
 
 pub fn framerate(input: &str) -> (u32, u32) {
 	if input.contains('/') {
@@ -16,38 +14,64 @@ pub fn framerate(input: &str) -> (u32, u32) {
 	}
 }
 
+// Order of the streams in the output, chapters are not a stream and come last
 pub fn streampriority(stream: &mkv::Stream) -> u32 {
-	if stream.streamtype == "video" {
-		1
-	} else if stream.streamtype == "audio" {
-		2
-	} else if stream.streamtype == "subtitle" {
-		3
-	} else {
-		4
+	match stream.streamtype {
+		StreamType::Video => 1,
+		StreamType::Audio => 2,
+		StreamType::Subtitle => 3,
+		StreamType::Attachment => 4,
+		StreamType::Data => 5,
+		StreamType::Chapters => 6,
+	}
+}
+
+// The speedup as an exact fraction, e.g. 24000/1001 -> 25 is 1001/960
+pub fn speedup(infps: (u32, u32), outfps: (u32, u32)) -> (u64, u64) {
+	reduce(
+		outfps.0 as u64 * infps.1 as u64,
+		outfps.1 as u64 * infps.0 as u64,
+	)
+}
+
+pub fn reduce(num: u64, den: u64) -> (u64, u64) {
+	let (mut a, mut b) = (num, den);
+	while b != 0 {
+		(a, b) = (b, a % b);
 	}
+
+	(num / a.max(1), den / a.max(1))
 }
 
-pub fn speedup(infps: (u32, u32), outfps: (u32, u32)) -> f32 {
-	(outfps.0 as f32 / outfps.1 as f32) / (infps.0 as f32 / infps.1 as f32)
+pub fn cmdline(tool: &str, args: &[String]) -> String {
+	let mut line = String::from(tool);
+
+	for arg in args {
+		line.push(' ');
+
+		if arg.is_empty() || arg.contains(char::is_whitespace) {
+			line.push_str(&format!("'{arg}'"));
+		} else {
+			line.push_str(arg);
+		}
+	}
+
+	line
 }
 
-pub fn check_output(
-	cmd: Result<std::process::Output, io::Error>,
-) -> Result<std::process::Output, io::Error> {
-	let cmd = cmd?;
+// FNV-1a, stable across runs and Rust versions unlike the std hasher
+pub fn fingerprint(parts: &[&str]) -> String {
+	let mut hash: u64 = 0xcbf29ce484222325;
 
-	if !cmd.status.success() {
-		return Err(io::Error::new(
-			io::ErrorKind::Other,
-			format!(
-				"Command exited with code {}",
-				cmd.status.code().unwrap_or(-1)
-			),
-		));
+	for part in parts {
+		// Separate the parts, so ["ab", "c"] and ["a", "bc"] differ
+		for byte in part.bytes().chain([0]) {
+			hash ^= byte as u64;
+			hash = hash.wrapping_mul(0x100000001b3);
+		}
 	}
 
-	Ok(cmd)
+	format!("{hash:016x}")
 }
 
 pub trait StrVec {